@@ -0,0 +1,44 @@
+-- Core blog schema. Uses IF NOT EXISTS so it can be adopted by databases
+-- that were created before migrations were introduced.
+
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY,
+    username VARCHAR(50) NOT NULL UNIQUE,
+    image TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(50) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS posts (
+    id UUID PRIMARY KEY,
+    title VARCHAR(255) NOT NULL,
+    body TEXT,
+    created_by UUID NOT NULL REFERENCES users (id),
+    slug VARCHAR(100) NOT NULL,
+    photo_url TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMPTZ,
+    published BOOLEAN NOT NULL DEFAULT FALSE,
+    view_count BIGINT NOT NULL DEFAULT 0,
+    like_count BIGINT NOT NULL DEFAULT 0,
+    bookmark_count BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS posts_created_by_slug_key ON posts (created_by, slug);
+CREATE INDEX IF NOT EXISTS posts_published_created_at_idx
+    ON posts (created_at DESC, id DESC) WHERE published = TRUE AND deleted_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS posts_to_tags (
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (post_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS posts_to_tags_tag_id_idx ON posts_to_tags (tag_id);
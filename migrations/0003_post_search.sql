@@ -0,0 +1,16 @@
+-- Weighted full-text search: title (A) > body (B) > tags (C) > author (D).
+-- The vector is maintained by the application on every post write.
+
+ALTER TABLE posts ADD COLUMN IF NOT EXISTS search_vector TSVECTOR;
+
+UPDATE posts p SET search_vector =
+    setweight(to_tsvector('english', COALESCE(p.title, '')), 'A') ||
+    setweight(to_tsvector('english', COALESCE(p.body, '')), 'B') ||
+    setweight(to_tsvector('english', COALESCE(
+        (SELECT string_agg(t.name, ' ') FROM posts_to_tags ptt
+         INNER JOIN tags t ON ptt.tag_id = t.id WHERE ptt.post_id = p.id), '')), 'C') ||
+    setweight(to_tsvector('english', u.username), 'D')
+FROM users u
+WHERE u.id = p.created_by AND p.search_vector IS NULL;
+
+CREATE INDEX IF NOT EXISTS posts_search_vector_idx ON posts USING GIN (search_vector);
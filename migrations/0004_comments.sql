@@ -0,0 +1,18 @@
+-- Threaded comments on posts
+
+CREATE TABLE comments (
+    id UUID PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES comments (id) ON DELETE CASCADE,
+    created_by UUID NOT NULL REFERENCES users (id),
+    body TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    deleted_at TIMESTAMPTZ
+);
+
+CREATE INDEX comments_post_id_created_at_idx ON comments (post_id, created_at);
+CREATE INDEX comments_parent_id_idx ON comments (parent_id);
+
+-- Number of non-deleted comments, maintained alongside comment writes
+ALTER TABLE posts ADD COLUMN comment_count BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,18 @@
+-- Likes and bookmarks; posts.like_count and posts.bookmark_count mirror these rows
+
+CREATE TABLE post_likes (
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (post_id, user_id)
+);
+
+CREATE TABLE post_bookmarks (
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (post_id, user_id)
+);
+
+CREATE INDEX post_likes_user_id_idx ON post_likes (user_id);
+CREATE INDEX post_bookmarks_user_id_idx ON post_bookmarks (user_id, created_at DESC);
@@ -0,0 +1,4 @@
+-- Roles gating editorial endpoints such as tag management
+
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user'
+    CHECK (role IN ('user', 'editor', 'admin'));
@@ -0,0 +1,3 @@
+-- Case-insensitive prefix lookups for tag autocomplete
+
+CREATE INDEX tags_lower_name_idx ON tags (lower(name) text_pattern_ops);
@@ -0,0 +1,10 @@
+-- Trigram title similarity for related posts. The extension is optional:
+-- servers without contrib modules keep working with RELATED_TITLE_SIMILARITY=false.
+
+DO $$
+BEGIN
+    CREATE EXTENSION IF NOT EXISTS pg_trgm;
+EXCEPTION WHEN OTHERS THEN
+    RAISE NOTICE 'pg_trgm is not available: %', SQLERRM;
+END
+$$;
@@ -0,0 +1,22 @@
+-- Engagement events and precomputed trending rankings
+
+CREATE TABLE post_events (
+    id BIGSERIAL PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('view', 'like', 'bookmark')),
+    -- Views are flushed in batches; removed likes and bookmarks are recorded as -1
+    count INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX post_events_created_at_idx ON post_events (created_at);
+
+CREATE TABLE trending_posts (
+    period TEXT NOT NULL,
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    score DOUBLE PRECISION NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (period, post_id)
+);
+
+CREATE INDEX trending_posts_period_score_idx ON trending_posts (period, score DESC);
@@ -0,0 +1,20 @@
+-- Replace the `published` flag with a status model and scheduled publishing
+
+ALTER TABLE posts ADD COLUMN status TEXT NOT NULL DEFAULT 'draft'
+    CHECK (status IN ('draft', 'scheduled', 'published', 'unlisted', 'archived'));
+ALTER TABLE posts ADD COLUMN publish_at TIMESTAMPTZ;
+
+UPDATE posts SET
+    status = CASE WHEN published THEN 'published' ELSE 'draft' END,
+    publish_at = CASE WHEN published THEN created_at END;
+
+ALTER TABLE posts ADD CONSTRAINT posts_scheduled_publish_at_check
+    CHECK (status <> 'scheduled' OR publish_at IS NOT NULL);
+
+-- Also drops posts_published_created_at_idx
+ALTER TABLE posts DROP COLUMN published;
+
+CREATE INDEX posts_listed_created_at_idx
+    ON posts (created_at DESC, id DESC) WHERE status = 'published' AND deleted_at IS NULL;
+CREATE INDEX posts_scheduled_publish_at_idx
+    ON posts (publish_at) WHERE status = 'scheduled' AND deleted_at IS NULL;
@@ -0,0 +1,22 @@
+-- Edit history: a snapshot of the title, body and tags after every change
+
+CREATE TABLE post_revisions (
+    id BIGSERIAL PRIMARY KEY,
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    body TEXT,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    created_by UUID NOT NULL REFERENCES users (id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (post_id, revision)
+);
+
+-- Existing posts start their history at their current content
+INSERT INTO post_revisions (post_id, revision, title, body, tags, created_by, created_at)
+SELECT p.id, 1, p.title, p.body,
+       ARRAY(SELECT t.name FROM posts_to_tags ptt
+             INNER JOIN tags t ON ptt.tag_id = t.id
+             WHERE ptt.post_id = p.id ORDER BY t.name),
+       p.created_by, p.updated_at
+FROM posts p;
@@ -0,0 +1,19 @@
+-- Previous post slugs and usernames, kept so old post URLs can redirect
+
+CREATE TABLE post_slug_history (
+    created_by UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    slug VARCHAR(100) NOT NULL,
+    post_id UUID NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (created_by, slug)
+);
+
+CREATE INDEX post_slug_history_post_id_idx ON post_slug_history (post_id);
+
+CREATE TABLE username_history (
+    username VARCHAR(50) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX username_history_user_id_idx ON username_history (user_id);
@@ -0,0 +1,8 @@
+-- Stored plain-text excerpts so listings never fetch full bodies. Excerpts of
+-- existing posts are generated by the application after startup.
+
+ALTER TABLE posts ADD COLUMN excerpt TEXT;
+-- Whether the author wrote the excerpt; generated ones follow body edits
+ALTER TABLE posts ADD COLUMN excerpt_custom BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX posts_missing_excerpt_idx ON posts (id) WHERE excerpt IS NULL AND body IS NOT NULL;
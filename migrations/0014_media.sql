@@ -0,0 +1,26 @@
+-- Uploaded images and the files generated from them
+
+CREATE TABLE media (
+    id UUID PRIMARY KEY,
+    created_by UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    content_type VARCHAR(50) NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX media_created_by_idx ON media (created_by, created_at DESC);
+
+-- The original upload plus its WebP and resized copies, stored as
+-- `{media_id}/{file_name}`
+CREATE TABLE media_variants (
+    media_id UUID NOT NULL REFERENCES media (id) ON DELETE CASCADE,
+    name VARCHAR(20) NOT NULL,
+    file_name VARCHAR(30) NOT NULL,
+    content_type VARCHAR(50) NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    size BIGINT NOT NULL,
+    PRIMARY KEY (media_id, name)
+);
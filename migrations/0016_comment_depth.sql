@@ -0,0 +1,20 @@
+-- Nesting level of each comment: 0 for top-level comments, at most 8
+-- (`MAX_COMMENT_DEPTH`). Existing replies nested deeper are moved onto their
+-- ancestor at depth 7 so every thread fits the limit.
+
+ALTER TABLE comments ADD COLUMN depth INTEGER NOT NULL DEFAULT 0;
+
+WITH RECURSIVE tree AS (
+    SELECT id, 0 AS depth, ARRAY[id] AS path FROM comments WHERE parent_id IS NULL
+    UNION ALL
+    SELECT c.id, t.depth + 1, t.path || c.id
+    FROM comments c
+    INNER JOIN tree t ON c.parent_id = t.id
+)
+UPDATE comments c
+SET depth = LEAST(t.depth, 8),
+    parent_id = CASE WHEN t.depth > 8 THEN t.path[8] ELSE c.parent_id END
+FROM tree t
+WHERE c.id = t.id AND t.depth > 0;
+
+CREATE INDEX comments_post_id_roots_idx ON comments (post_id, created_at) WHERE parent_id IS NULL;
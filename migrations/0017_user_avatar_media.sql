@@ -0,0 +1,10 @@
+-- The upload a user's `image` points to, so replacing or removing the avatar
+-- can delete it. Deleting the media clears it.
+
+ALTER TABLE users ADD COLUMN avatar_media_id UUID REFERENCES media (id) ON DELETE SET NULL;
+
+UPDATE users u
+SET avatar_media_id = m.id
+FROM media m
+WHERE m.created_by = u.id
+  AND u.image LIKE '%/media/' || m.id || '/%';
@@ -0,0 +1,6 @@
+-- Listings and feeds order posts by when they were published, falling back to
+-- when they were written
+
+CREATE INDEX posts_listed_published_at_idx
+    ON posts ((COALESCE(publish_at, created_at)) DESC, id DESC)
+    WHERE status = 'published' AND deleted_at IS NULL;
@@ -0,0 +1,252 @@
+use crate::config::JwtConfig;
+use crate::error::AppError;
+use crate::models::user::{Role, User};
+use crate::state::AppState;
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng, rand_core::RngCore},
+};
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderMap, header::AUTHORIZATION, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Claims carried by access tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: Uuid,
+    pub exp: i64,
+    pub iat: i64,
+}
+
+/// Keys and validation rules used to verify and issue bearer tokens
+pub struct JwtKeys {
+    decoding: DecodingKey,
+    encoding: Option<EncodingKey>,
+    header: Header,
+    validation: Validation,
+    access_token_ttl: Duration,
+}
+
+impl JwtKeys {
+    /// Build verification keys from configuration.
+    ///
+    /// Returns `Ok(None)` when the key required by the configured algorithm is absent,
+    /// which disables authentication. With RS256 and no private key the keys can
+    /// verify tokens but not issue them.
+    ///
+    /// # Errors
+    /// Returns an error if a configured RSA key is not valid PEM.
+    pub fn from_config(config: &JwtConfig) -> Result<Option<Self>, jsonwebtoken::errors::Error> {
+        let (decoding, encoding) = match config.algorithm {
+            Algorithm::RS256 => {
+                let Some(public) = &config.public_key_pem else {
+                    return Ok(None);
+                };
+                let encoding = config
+                    .private_key_pem
+                    .as_ref()
+                    .map(|pem| EncodingKey::from_rsa_pem(pem.as_bytes()))
+                    .transpose()?;
+                (DecodingKey::from_rsa_pem(public.as_bytes())?, encoding)
+            }
+            _ => {
+                let Some(secret) = &config.secret else {
+                    return Ok(None);
+                };
+                (
+                    DecodingKey::from_secret(secret.as_bytes()),
+                    Some(EncodingKey::from_secret(secret.as_bytes())),
+                )
+            }
+        };
+
+        Ok(Some(Self {
+            decoding,
+            encoding,
+            header: Header::new(config.algorithm),
+            validation: Validation::new(config.algorithm),
+            access_token_ttl: config.access_token_ttl,
+        }))
+    }
+
+    /// Lifetime of issued access tokens in seconds
+    pub fn access_token_ttl_secs(&self) -> i64 {
+        self.access_token_ttl.as_secs() as i64
+    }
+
+    /// Issue a signed access token for a user
+    pub fn issue(&self, user_id: Uuid) -> Result<String, AppError> {
+        let encoding = self.encoding.as_ref().ok_or_else(|| {
+            AppError::InternalServerError(String::from("Token issuing is not configured"))
+        })?;
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: user_id,
+            iat: now,
+            exp: now + self.access_token_ttl_secs(),
+        };
+
+        encode(&self.header, &claims, encoding).map_err(|e| {
+            tracing::error!("Failed to sign access token: {}", e);
+            AppError::InternalServerError(String::from("Failed to issue token"))
+        })
+    }
+
+    /// Verify a token signature and expiry, returning its claims
+    pub fn verify(&self, token: &str) -> Result<Claims, AppError> {
+        decode::<Claims>(token, &self.decoding, &self.validation)
+            .map(|data| data.claims)
+            .map_err(|e| {
+                tracing::debug!("Rejected bearer token: {}", e);
+                AppError::Unauthorized(String::from("Invalid or expired token"))
+            })
+    }
+}
+
+/// Hash a password with Argon2id and a random salt, returning the PHC string
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| {
+            tracing::error!("Failed to hash password: {}", e);
+            AppError::InternalServerError(String::from("Failed to hash password"))
+        })
+}
+
+/// Hash checked when logging in as a user without one, so that unknown
+/// usernames take as long to reject as wrong passwords. Uses the same
+/// parameters as `hash_password`; no password is known to match it.
+pub const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=19456,t=2,p=1$rgh5mgwG9lLMdvCoA4diRw$zJPuISBLZjZPKcHct2UiTFIO8qvdiPcnjBfh7qsfcuA";
+
+/// Check a password against a stored PHC hash string
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Generate an opaque refresh token (256 random bits, base64url encoded)
+pub fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a refresh token for storage; only the hash is ever persisted
+pub fn hash_refresh_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Extract the token from an `Authorization: Bearer <token>` header
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty()).then_some(token)
+}
+
+/// Why a supplied bearer token was not accepted, kept for `CurrentUser`
+#[derive(Clone, Copy)]
+struct RejectedToken(&'static str);
+
+/// Middleware that verifies a bearer token when one is present.
+///
+/// Valid tokens store their `Claims` in the request extensions for the
+/// `CurrentUser` extractor. Requests without a valid token pass through
+/// anonymously, so public routes still answer them; `CurrentUser` rejects
+/// them with 401 on routes that need a caller.
+pub async fn authenticate(State(state): State<AppState>, mut req: Request, next: Next) -> Response {
+    if let Some(token) = bearer_token(req.headers()) {
+        let verified = match state.jwt.as_ref() {
+            Some(keys) => keys
+                .verify(token)
+                .map_err(|_| RejectedToken("Invalid or expired token")),
+            None => Err(RejectedToken("Authentication is not configured")),
+        };
+        match verified {
+            Ok(claims) => {
+                req.extensions_mut().insert(claims);
+            }
+            Err(rejected) => {
+                req.extensions_mut().insert(rejected);
+            }
+        }
+    }
+
+    next.run(req).await
+}
+
+/// The authenticated caller, resolved from the claims stored by `authenticate`.
+/// Rejects the request with 401 when no valid token was supplied.
+pub struct CurrentUser {
+    pub user: User,
+    pub role: Role,
+}
+
+impl CurrentUser {
+    /// Whether the caller may manage shared content such as tags
+    pub fn is_editor(&self) -> bool {
+        self.role >= Role::Editor
+    }
+
+    /// Whether the caller may act on other users' posts
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+}
+
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, AppError> {
+        let claims = parts.extensions.get::<Claims>().ok_or_else(|| {
+            let reason = parts
+                .extensions
+                .get::<RejectedToken>()
+                .map_or("Missing bearer token", |r| r.0);
+            AppError::Unauthorized(String::from(reason))
+        })?;
+
+        let client = state.pool.get().await?;
+        let row = client
+            .query_opt(
+                "SELECT id, username, image, role FROM users WHERE id = $1",
+                &[&claims.sub],
+            )
+            .await?
+            .ok_or_else(|| AppError::Unauthorized(String::from("User no longer exists")))?;
+
+        Ok(Self {
+            user: User::from(&row),
+            role: Role::from_db(row.get(3)),
+        })
+    }
+}
+
+/// The id of the caller if a valid bearer token was supplied, for endpoints that
+/// personalise their response but also serve anonymous readers.
+pub struct Viewer(pub Option<Uuid>);
+
+impl<S: Send + Sync> FromRequestParts<S> for Viewer {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, AppError> {
+        Ok(Self(parts.extensions.get::<Claims>().map(|c| c.sub)))
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A small in-memory cache whose entries expire `ttl` after insertion.
+///
+/// Holds at most `capacity` entries and, when built with `with_max_weight`,
+/// at most that total weight; when full, expired entries are dropped first and
+/// then the oldest entries are evicted.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    max_weight: usize,
+    weigh: fn(&V) -> usize,
+    entries: Mutex<Entries<K, V>>,
+}
+
+struct Entries<K, V> {
+    map: HashMap<K, (Instant, V)>,
+    weight: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            max_weight: usize::MAX,
+            weigh: |_| 0,
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                weight: 0,
+            }),
+        }
+    }
+
+    /// Also bound the summed `weigh` of all entries, e.g. their size in bytes.
+    /// Values heavier than `max_weight` on their own are not cached.
+    pub fn with_max_weight(mut self, max_weight: usize, weigh: fn(&V) -> usize) -> Self {
+        self.max_weight = max_weight;
+        self.weigh = weigh;
+        self
+    }
+
+    /// Return a copy of the cached value if it has not expired
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .map
+            .get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let weight = (self.weigh)(&value);
+        if self.ttl.is_zero() || self.capacity == 0 || weight > self.max_weight {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let Entries { map, weight: total } = &mut *entries;
+        if let Some((_, old)) = map.remove(&key) {
+            *total -= (self.weigh)(&old);
+        }
+        let full = |map: &HashMap<K, (Instant, V)>, total: usize| {
+            map.len() >= self.capacity || total + weight > self.max_weight
+        };
+        if full(map, *total) {
+            let ttl = self.ttl;
+            let weigh = self.weigh;
+            map.retain(|_, (inserted, value)| {
+                let live = inserted.elapsed() < ttl;
+                if !live {
+                    *total -= weigh(value);
+                }
+                live
+            });
+        }
+        while full(map, *total) {
+            let Some(oldest) = map
+                .iter()
+                .min_by_key(|(_, (inserted, _))| *inserted)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            if let Some((_, old)) = map.remove(&oldest) {
+                *total -= (self.weigh)(&old);
+            }
+        }
+        *total += weight;
+        map.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize, max_weight: usize) -> TtlCache<u32, String> {
+        TtlCache::new(Duration::from_secs(60), capacity).with_max_weight(max_weight, String::len)
+    }
+
+    #[test]
+    fn evicts_oldest_entries_beyond_the_weight_budget() {
+        let cache = cache(10, 10);
+        cache.insert(1, String::from("aaaa"));
+        cache.insert(2, String::from("bbbb"));
+        cache.insert(3, String::from("cccc"));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).as_deref(), Some("bbbb"));
+        assert_eq!(cache.get(&3).as_deref(), Some("cccc"));
+    }
+
+    #[test]
+    fn replacing_an_entry_releases_its_weight() {
+        let cache = cache(10, 10);
+        cache.insert(1, String::from("aaaaaaaa"));
+        cache.insert(1, String::from("aa"));
+        cache.insert(2, String::from("bbbbbbbb"));
+        assert_eq!(cache.get(&1).as_deref(), Some("aa"));
+        assert_eq!(cache.get(&2).as_deref(), Some("bbbbbbbb"));
+    }
+
+    #[test]
+    fn skips_values_heavier_than_the_whole_budget() {
+        let cache = cache(10, 4);
+        cache.insert(1, String::from("aaaa"));
+        cache.insert(2, String::from("bbbbb"));
+        assert_eq!(cache.get(&1).as_deref(), Some("aaaa"));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn evicts_the_oldest_entry_at_capacity() {
+        let cache = cache(2, usize::MAX);
+        cache.insert(1, String::new());
+        cache.insert(2, String::new());
+        cache.insert(3, String::new());
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.get(&2).is_some() && cache.get(&3).is_some());
+    }
+}
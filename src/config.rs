@@ -1,5 +1,9 @@
+use jsonwebtoken::Algorithm;
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 use std::time::Duration;
+use uuid::Uuid;
 
 // ============================================================================
 // Constants
@@ -10,6 +14,25 @@ const DEFAULT_DATABASE_URL: &str =
     "host=localhost user=postgres password=postgres dbname=axumbackend";
 const DEFAULT_POOL_MAX_SIZE: usize = 20;
 const DEFAULT_CONNECTION_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_ACCESS_TOKEN_TTL_SECS: u64 = 15 * 60;
+const DEFAULT_REFRESH_TOKEN_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+const DEFAULT_VIEW_DEDUP_WINDOW_SECS: u64 = 30 * 60;
+const DEFAULT_VIEW_FLUSH_INTERVAL_SECS: u64 = 10;
+const DEFAULT_VIEW_MAX_VISITORS: usize = 100_000;
+const DEFAULT_SITE_URL: &str = "http://localhost:8080";
+const DEFAULT_SITE_TITLE: &str = "Axum Backend";
+const DEFAULT_FEED_ITEM_LIMIT: i64 = 20;
+const MAX_FEED_ITEM_LIMIT: i64 = 100;
+const DEFAULT_RELATED_CACHE_TTL_SECS: u64 = 5 * 60;
+const DEFAULT_RENDER_CACHE_TTL_SECS: u64 = 60 * 60;
+const DEFAULT_TRENDING_REFRESH_INTERVAL_SECS: u64 = 5 * 60;
+const DEFAULT_PUBLISH_INTERVAL_SECS: u64 = 30;
+const DEFAULT_TRASH_RETENTION_SECS: u64 = 30 * 24 * 60 * 60;
+const DEFAULT_TRASH_PURGE_INTERVAL_SECS: u64 = 60 * 60;
+const DEFAULT_MEDIA_DIR: &str = "media";
+const DEFAULT_MEDIA_MAX_UPLOAD_SIZE: usize = 10 * 1024 * 1024;
+const DEFAULT_MEDIA_VARIANT_WIDTHS: &str = "320,640,1280";
+const DEFAULT_MEDIA_PROCESSING_CONCURRENCY: usize = 2;
 
 // ============================================================================
 // Configuration Structures
@@ -21,6 +44,21 @@ pub struct Config {
     pub port: u16,
     pub database_url: String,
     pub db_pool: PoolConfig,
+    pub run_migrations: bool,
+    pub jwt: JwtConfig,
+    pub views: ViewConfig,
+    pub trust_proxy_headers: bool,
+    pub site: SiteConfig,
+    pub feed: FeedConfig,
+    pub related: RelatedConfig,
+    /// How long rendered post bodies are cached
+    pub render_cache_ttl: Duration,
+    /// How often trending rankings are recomputed
+    pub trending_refresh_interval: Duration,
+    /// How often due scheduled posts are published
+    pub publish_interval: Duration,
+    pub trash: TrashConfig,
+    pub media: MediaConfig,
 }
 
 /// Database connection pool configuration
@@ -30,6 +68,76 @@ pub struct PoolConfig {
     pub connection_timeout: Duration,
 }
 
+/// JWT bearer-token configuration
+#[derive(Debug, Clone)]
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub secret: Option<String>,
+    pub public_key_pem: Option<String>,
+    pub private_key_pem: Option<String>,
+    pub access_token_ttl: Duration,
+    pub refresh_token_ttl: Duration,
+}
+
+/// Post view counting configuration
+#[derive(Debug, Clone)]
+pub struct ViewConfig {
+    pub dedup_window: Duration,
+    /// Most visitors remembered for deduplication; the oldest are forgotten past it
+    pub max_visitors: usize,
+    pub flush_interval: Duration,
+}
+
+/// Public site identity used in syndication feeds
+#[derive(Debug, Clone)]
+pub struct SiteConfig {
+    /// Public base URL without a trailing slash
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    /// Contents served as `/robots.txt` instead of the generated file
+    pub robots_txt: Option<String>,
+    /// Paths disallowed for all crawlers in the generated `/robots.txt`
+    pub robots_disallow: Vec<String>,
+}
+
+/// RSS, Atom and JSON Feed configuration
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    /// Include the whole post body instead of the 200-character excerpt
+    pub full_content: bool,
+    pub item_limit: i64,
+}
+
+/// Related posts scoring and caching configuration
+#[derive(Debug, Clone)]
+pub struct RelatedConfig {
+    /// Score title similarity with `pg_trgm`
+    pub title_similarity: bool,
+    pub cache_ttl: Duration,
+}
+
+/// Retention of soft-deleted posts
+#[derive(Debug, Clone)]
+pub struct TrashConfig {
+    /// How long deleted posts stay restorable; zero keeps them forever
+    pub retention: Duration,
+    pub purge_interval: Duration,
+}
+
+/// Image upload storage and processing
+#[derive(Debug, Clone)]
+pub struct MediaConfig {
+    /// Directory uploaded files are stored in
+    pub dir: PathBuf,
+    /// Largest accepted upload in bytes
+    pub max_upload_size: usize,
+    /// Widths of the resized WebP copies made of each upload
+    pub variant_widths: Vec<u32>,
+    /// Number of uploads decoded and resized at the same time
+    pub processing_concurrency: usize,
+}
+
 // ============================================================================
 // Implementation
 // ============================================================================
@@ -42,15 +150,68 @@ impl Config {
     /// - `DATABASE_URL`: PostgreSQL connection string
     /// - `DB_POOL_MAX_SIZE`: Maximum pool size (default: 20)
     /// - `DB_POOL_CONNECTION_TIMEOUT`: Connection timeout in seconds (default: 30)
+    /// - `RUN_MIGRATIONS`: Apply pending schema migrations at startup (default: false)
+    /// - `JWT_ALGORITHM`: Token signing algorithm, `HS256` or `RS256` (default: HS256)
+    /// - `JWT_SECRET`: Shared secret for HS256 tokens
+    /// - `JWT_PUBLIC_KEY_PATH`: Path to the PEM public key for RS256 tokens
+    /// - `JWT_PRIVATE_KEY_PATH`: Path to the PEM private key used to issue RS256 tokens
+    /// - `JWT_ACCESS_TOKEN_TTL`: Access token lifetime in seconds (default: 900)
+    /// - `JWT_REFRESH_TOKEN_TTL`: Refresh token lifetime in seconds (default: 2592000)
+    /// - `VIEW_DEDUP_WINDOW`: Seconds during which repeat views by one visitor count once (default: 1800)
+    /// - `VIEW_MAX_VISITORS`: Most recent visitors remembered for deduplication (default: 100000)
+    /// - `VIEW_FLUSH_INTERVAL`: Seconds between writes of buffered view counts (default: 10)
+    /// - `TRUST_PROXY_HEADERS`: Take the client IP from the last `X-Forwarded-For` hop (default: false)
+    /// - `SITE_URL`: Public base URL used for links in feeds (default: http://localhost:8080)
+    /// - `SITE_TITLE`: Site name used as the feed title (default: Axum Backend)
+    /// - `SITE_DESCRIPTION`: Site description used in feeds (default: empty)
+    /// - `ROBOTS_TXT_PATH`: File served verbatim as `/robots.txt`
+    /// - `ROBOTS_DISALLOW`: Comma-separated paths disallowed in the generated `/robots.txt`
+    /// - `FEED_FULL_CONTENT`: Put full post bodies in feeds instead of excerpts (default: false)
+    /// - `FEED_ITEM_LIMIT`: Number of posts per feed, at most 100 (default: 20)
+    /// - `RELATED_TITLE_SIMILARITY`: Score related posts by trigram title similarity, ignored without `pg_trgm` (default: false)
+    /// - `RELATED_CACHE_TTL`: Seconds to cache related posts per post, 0 disables (default: 300)
+    /// - `RENDER_CACHE_TTL`: Seconds to cache rendered HTML and text bodies, 0 disables (default: 3600)
+    /// - `TRENDING_REFRESH_INTERVAL`: Seconds between trending recomputations (default: 300)
+    /// - `PUBLISH_INTERVAL`: Seconds between checks for due scheduled posts (default: 30)
+    /// - `TRASH_RETENTION`: Seconds deleted posts are kept before being purged, 0 keeps them (default: 2592000)
+    /// - `TRASH_PURGE_INTERVAL`: Seconds between purges of expired deleted posts (default: 3600)
+    /// - `MEDIA_DIR`: Directory uploaded images are stored in (default: media)
+    /// - `MEDIA_MAX_UPLOAD_SIZE`: Largest accepted upload in bytes (default: 10485760)
+    /// - `MEDIA_VARIANT_WIDTHS`: Comma-separated widths of resized WebP copies (default: 320,640,1280)
+    /// - `MEDIA_PROCESSING_CONCURRENCY`: Uploads processed at the same time, others wait (default: 2)
     ///
     /// # Panics
-    /// Panics if numeric values cannot be parsed.
+    /// Panics if numeric values cannot be parsed, the JWT algorithm is unsupported,
+    /// or a configured key file cannot be read.
     pub fn from_env() -> Self {
         Self {
             port: parse_u16("PORT", DEFAULT_PORT),
             database_url: env::var("DATABASE_URL")
                 .unwrap_or_else(|_| DEFAULT_DATABASE_URL.to_string()),
             db_pool: PoolConfig::from_env(),
+            run_migrations: parse_bool("RUN_MIGRATIONS", false),
+            jwt: JwtConfig::from_env(),
+            views: ViewConfig::from_env(),
+            trust_proxy_headers: parse_bool("TRUST_PROXY_HEADERS", false),
+            site: SiteConfig::from_env(),
+            feed: FeedConfig::from_env(),
+            related: RelatedConfig::from_env(),
+            render_cache_ttl: Duration::from_secs(parse_u64(
+                "RENDER_CACHE_TTL",
+                DEFAULT_RENDER_CACHE_TTL_SECS,
+            )),
+            trending_refresh_interval: Duration::from_secs(
+                parse_u64(
+                    "TRENDING_REFRESH_INTERVAL",
+                    DEFAULT_TRENDING_REFRESH_INTERVAL_SECS,
+                )
+                .max(1),
+            ),
+            publish_interval: Duration::from_secs(
+                parse_u64("PUBLISH_INTERVAL", DEFAULT_PUBLISH_INTERVAL_SECS).max(1),
+            ),
+            trash: TrashConfig::from_env(),
+            media: MediaConfig::from_env(),
         }
     }
 }
@@ -67,6 +228,156 @@ impl PoolConfig {
     }
 }
 
+impl JwtConfig {
+    fn from_env() -> Self {
+        let algorithm = match env::var("JWT_ALGORITHM").as_deref() {
+            Err(_) | Ok("HS256") => Algorithm::HS256,
+            Ok("RS256") => Algorithm::RS256,
+            Ok(other) => panic!("JWT_ALGORITHM must be HS256 or RS256, got {other}"),
+        };
+
+        Self {
+            algorithm,
+            secret: env::var("JWT_SECRET").ok().filter(|s| !s.is_empty()),
+            public_key_pem: read_optional_file("JWT_PUBLIC_KEY_PATH"),
+            private_key_pem: read_optional_file("JWT_PRIVATE_KEY_PATH"),
+            access_token_ttl: Duration::from_secs(parse_u64(
+                "JWT_ACCESS_TOKEN_TTL",
+                DEFAULT_ACCESS_TOKEN_TTL_SECS,
+            )),
+            refresh_token_ttl: Duration::from_secs(parse_u64(
+                "JWT_REFRESH_TOKEN_TTL",
+                DEFAULT_REFRESH_TOKEN_TTL_SECS,
+            )),
+        }
+    }
+}
+
+impl ViewConfig {
+    fn from_env() -> Self {
+        Self {
+            dedup_window: Duration::from_secs(parse_u64(
+                "VIEW_DEDUP_WINDOW",
+                DEFAULT_VIEW_DEDUP_WINDOW_SECS,
+            )),
+            max_visitors: parse_usize("VIEW_MAX_VISITORS", DEFAULT_VIEW_MAX_VISITORS).max(1),
+            flush_interval: Duration::from_secs(
+                parse_u64("VIEW_FLUSH_INTERVAL", DEFAULT_VIEW_FLUSH_INTERVAL_SECS).max(1),
+            ),
+        }
+    }
+}
+
+impl SiteConfig {
+    fn from_env() -> Self {
+        Self {
+            url: env::var("SITE_URL")
+                .unwrap_or_else(|_| DEFAULT_SITE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            title: env::var("SITE_TITLE").unwrap_or_else(|_| DEFAULT_SITE_TITLE.to_string()),
+            description: env::var("SITE_DESCRIPTION").unwrap_or_default(),
+            robots_txt: read_optional_file("ROBOTS_TXT_PATH"),
+            robots_disallow: env::var("ROBOTS_DISALLOW")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    /// Public URL of a post, served by `/v1/posts/u/{username}/{slug}`
+    pub fn post_url(&self, username: &str, slug: &str) -> String {
+        format!("{}/v1/posts/u/{}/{}", self.url, username, slug)
+    }
+
+    /// Public URL of an author's profile, served by `/v1/users/{username}`
+    pub fn author_url(&self, username: &str) -> String {
+        format!("{}/v1/users/{}", self.url, username)
+    }
+
+    /// Public URL of a stored media file
+    pub fn media_url(&self, media_id: Uuid, file_name: &str) -> String {
+        format!("{}/media/{}/{}", self.url, media_id, file_name)
+    }
+
+    /// Public URL of a tag's posts, served by `/v1/posts/tag/{tag}`
+    pub fn tag_url(&self, tag: &str) -> String {
+        format!("{}/v1/posts/tag/{}", self.url, tag)
+    }
+}
+
+impl FeedConfig {
+    fn from_env() -> Self {
+        Self {
+            full_content: parse_bool("FEED_FULL_CONTENT", false),
+            item_limit: (parse_u64("FEED_ITEM_LIMIT", DEFAULT_FEED_ITEM_LIMIT as u64) as i64)
+                .clamp(1, MAX_FEED_ITEM_LIMIT),
+        }
+    }
+}
+
+impl RelatedConfig {
+    fn from_env() -> Self {
+        Self {
+            title_similarity: parse_bool("RELATED_TITLE_SIMILARITY", false),
+            cache_ttl: Duration::from_secs(parse_u64(
+                "RELATED_CACHE_TTL",
+                DEFAULT_RELATED_CACHE_TTL_SECS,
+            )),
+        }
+    }
+}
+
+impl TrashConfig {
+    fn from_env() -> Self {
+        Self {
+            retention: Duration::from_secs(parse_u64(
+                "TRASH_RETENTION",
+                DEFAULT_TRASH_RETENTION_SECS,
+            )),
+            purge_interval: Duration::from_secs(
+                parse_u64("TRASH_PURGE_INTERVAL", DEFAULT_TRASH_PURGE_INTERVAL_SECS).max(1),
+            ),
+        }
+    }
+}
+
+impl MediaConfig {
+    fn from_env() -> Self {
+        let mut variant_widths: Vec<u32> = env::var("MEDIA_VARIANT_WIDTHS")
+            .unwrap_or_else(|_| DEFAULT_MEDIA_VARIANT_WIDTHS.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .map(|w| {
+                w.parse::<u32>()
+                    .ok()
+                    .filter(|w| *w > 0)
+                    .unwrap_or_else(|| panic!("MEDIA_VARIANT_WIDTHS must list positive widths"))
+            })
+            .collect();
+        // Widest first, matching the order variants are listed in
+        variant_widths.sort_unstable_by(|a, b| b.cmp(a));
+        variant_widths.dedup();
+
+        Self {
+            dir: PathBuf::from(
+                env::var("MEDIA_DIR").unwrap_or_else(|_| DEFAULT_MEDIA_DIR.to_string()),
+            ),
+            max_upload_size: parse_usize("MEDIA_MAX_UPLOAD_SIZE", DEFAULT_MEDIA_MAX_UPLOAD_SIZE),
+            variant_widths,
+            processing_concurrency: parse_usize(
+                "MEDIA_PROCESSING_CONCURRENCY",
+                DEFAULT_MEDIA_PROCESSING_CONCURRENCY,
+            )
+            .max(1),
+        }
+    }
+}
+
 // ============================================================================
 // Helper Functions
 // ============================================================================
@@ -94,3 +405,22 @@ fn parse_usize(key: &str, default: usize) -> usize {
         .parse::<usize>()
         .unwrap_or_else(|_| panic!("{key} must be a valid usize number"))
 }
+
+/// Parse an environment variable as a boolean (`true`/`false`/`1`/`0`) with default fallback.
+fn parse_bool(key: &str, default: bool) -> bool {
+    match env::var(key).as_deref() {
+        Err(_) | Ok("") => default,
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") => false,
+        Ok(_) => panic!("{key} must be true or false"),
+    }
+}
+
+/// Read the file named by an environment variable, if the variable is set.
+fn read_optional_file(key: &str) -> Option<String> {
+    let path = env::var(key).ok().filter(|p| !p.is_empty())?;
+    Some(
+        fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("{key} points to an unreadable file {path}: {e}")),
+    )
+}
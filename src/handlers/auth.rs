@@ -0,0 +1,190 @@
+use super::post::USERNAME_RE;
+use crate::auth;
+use crate::database::DbPool;
+use crate::error::AppError;
+use crate::models::auth::AuthTokens;
+use crate::models::user::User;
+use crate::response::ApiResponse;
+use crate::services;
+use crate::services::auth::RefreshOutcome;
+use crate::state::AppState;
+use axum::{Json, Router, extract::State, routing::post};
+use axum_valid::Valid;
+use chrono::Utc;
+use serde::Deserialize;
+use tokio_postgres::Client;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+pub struct RegisterRequest {
+    #[validate(length(min = 3, max = 50), regex(path = *USERNAME_RE))]
+    username: String,
+    #[validate(length(min = 8, max = 128))]
+    password: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct LoginRequest {
+    #[validate(length(min = 1, max = 50))]
+    username: String,
+    #[validate(length(min = 1, max = 128))]
+    password: String,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshRequest {
+    #[validate(length(min = 1, max = 128))]
+    refresh_token: String,
+}
+
+/// Run Argon2 work off the async runtime
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, AppError> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Worker task failed: {}", e)))
+}
+
+fn jwt_keys(state: &AppState) -> Result<&auth::JwtKeys, AppError> {
+    state.jwt.as_deref().ok_or_else(|| {
+        AppError::InternalServerError(String::from("Authentication is not configured"))
+    })
+}
+
+/// Issue an access token plus a refresh token starting a new token family
+async fn issue_tokens(
+    state: &AppState,
+    client: &Client,
+    user: User,
+) -> Result<AuthTokens, AppError> {
+    let keys = jwt_keys(state)?;
+    let access_token = keys.issue(user.id)?;
+    let refresh_token = auth::generate_refresh_token();
+    let expires_at = Utc::now() + state.config.jwt.refresh_token_ttl;
+
+    services::auth::store_refresh_token(
+        client,
+        user.id,
+        &auth::hash_refresh_token(&refresh_token),
+        expires_at,
+    )
+    .await?;
+
+    Ok(AuthTokens {
+        user,
+        access_token,
+        refresh_token,
+        token_type: String::from("Bearer"),
+        expires_in: keys.access_token_ttl_secs(),
+    })
+}
+
+pub async fn register(
+    State(state): State<AppState>,
+    State(pool): State<DbPool>,
+    Valid(Json(input)): Valid<Json<RegisterRequest>>,
+) -> Result<Json<ApiResponse<AuthTokens>>, AppError> {
+    jwt_keys(&state)?;
+    let password = input.password;
+    let password_hash = blocking(move || auth::hash_password(&password)).await??;
+
+    let client = pool.get().await?;
+    let user = services::auth::create_user(&client, &input.username, &password_hash)
+        .await?
+        .ok_or_else(|| AppError::Conflict(format!("Username already taken: {}", input.username)))?;
+
+    let tokens = issue_tokens(&state, &client, user).await?;
+    Ok(Json(ApiResponse::success(tokens)))
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    State(pool): State<DbPool>,
+    Valid(Json(input)): Valid<Json<LoginRequest>>,
+) -> Result<Json<ApiResponse<AuthTokens>>, AppError> {
+    let client = pool.get().await?;
+    let credentials = services::auth::find_user_credentials(&client, &input.username).await?;
+
+    let (user, password_hash) = match credentials {
+        Some((user, Some(password_hash))) => (Some(user), password_hash),
+        // Still verify a password so the response time doesn't reveal
+        // whether the username exists
+        _ => (None, String::from(auth::DUMMY_PASSWORD_HASH)),
+    };
+
+    let password = input.password;
+    let valid = blocking(move || auth::verify_password(&password, &password_hash)).await?;
+    let Some(user) = user.filter(|_| valid) else {
+        return Err(AppError::Unauthorized(String::from(
+            "Invalid username or password",
+        )));
+    };
+
+    let tokens = issue_tokens(&state, &client, user).await?;
+    Ok(Json(ApiResponse::success(tokens)))
+}
+
+pub async fn refresh(
+    State(state): State<AppState>,
+    State(pool): State<DbPool>,
+    Valid(Json(input)): Valid<Json<RefreshRequest>>,
+) -> Result<Json<ApiResponse<AuthTokens>>, AppError> {
+    let keys = jwt_keys(&state)?;
+    let mut client = pool.get().await?;
+
+    let refresh_token = auth::generate_refresh_token();
+    let expires_at = Utc::now() + state.config.jwt.refresh_token_ttl;
+    let outcome = services::auth::rotate_refresh_token(
+        &mut client,
+        &auth::hash_refresh_token(&input.refresh_token),
+        &auth::hash_refresh_token(&refresh_token),
+        expires_at,
+    )
+    .await?;
+
+    let user = match outcome {
+        RefreshOutcome::Rotated(user) => user,
+        RefreshOutcome::Reused => {
+            return Err(AppError::Unauthorized(String::from(
+                "Refresh token has already been used; please log in again",
+            )));
+        }
+        RefreshOutcome::Invalid => {
+            return Err(AppError::Unauthorized(String::from(
+                "Invalid or expired refresh token",
+            )));
+        }
+    };
+
+    let access_token = keys.issue(user.id)?;
+    Ok(Json(ApiResponse::success(AuthTokens {
+        user,
+        access_token,
+        refresh_token,
+        token_type: String::from("Bearer"),
+        expires_in: keys.access_token_ttl_secs(),
+    })))
+}
+
+pub async fn logout(
+    State(pool): State<DbPool>,
+    Valid(Json(input)): Valid<Json<RefreshRequest>>,
+) -> Result<Json<ApiResponse<bool>>, AppError> {
+    let client = pool.get().await?;
+    let revoked = services::auth::revoke_refresh_token(
+        &client,
+        &auth::hash_refresh_token(&input.refresh_token),
+    )
+    .await?;
+    Ok(Json(ApiResponse::success(revoked)))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/v1/auth/register", post(register))
+        .route("/v1/auth/login", post(login))
+        .route("/v1/auth/refresh", post(refresh))
+        .route("/v1/auth/logout", post(logout))
+}
@@ -0,0 +1,168 @@
+use crate::auth::CurrentUser;
+use crate::database::DbPool;
+use crate::error::AppError;
+use crate::models::comment::Comment;
+use crate::response::ApiResponse;
+use crate::services;
+use crate::services::comment::CommentOutcome;
+use crate::state::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, patch},
+};
+use axum_valid::Valid;
+use serde::Deserialize;
+use tokio_postgres::Client;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCommentRequest {
+    #[validate(length(max = 10_000), custom(function = "validate_body"))]
+    body: String,
+    parent_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateCommentRequest {
+    #[validate(length(max = 10_000), custom(function = "validate_body"))]
+    body: String,
+}
+
+/// Reject bodies that are empty once trimmed, as they are stored trimmed
+fn validate_body(body: &str) -> Result<(), ValidationError> {
+    if body.trim().is_empty() {
+        Err(ValidationError::new("blank"))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct CommentPageQuery {
+    #[validate(range(min = 0, max = 10_000))]
+    offset: Option<i64>,
+    #[validate(range(min = 1, max = 100))]
+    limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct PostIdPath {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct CommentIdPath {
+    pub id: Uuid,
+}
+
+/// Ensure the post exists and is open for reading and commenting
+async fn ensure_post_visible(client: &Client, post_id: Uuid) -> Result<(), AppError> {
+    if services::post::post_is_visible(client, post_id).await? {
+        Ok(())
+    } else {
+        Err(AppError::NotFound(format!("Post not found: {}", post_id)))
+    }
+}
+
+/// Ensure the comment exists and is owned by the caller
+async fn ensure_comment_owner(
+    client: &Client,
+    id: Uuid,
+    current: &CurrentUser,
+) -> Result<(), AppError> {
+    match services::comment::get_comment_owner(client, id).await? {
+        Some(owner) if owner == current.user.id => Ok(()),
+        Some(_) => Err(AppError::Forbidden(String::from(
+            "You can only modify your own comments",
+        ))),
+        None => Err(AppError::NotFound(format!("Comment not found: {}", id))),
+    }
+}
+
+/// Page through the top-level comments of a post, each with all its replies
+pub async fn get_comments(
+    State(pool): State<DbPool>,
+    Path(path): Path<PostIdPath>,
+    Valid(Query(query)): Valid<Query<CommentPageQuery>>,
+) -> Result<Json<ApiResponse<Vec<Comment>>>, AppError> {
+    let client = pool.get().await?;
+    ensure_post_visible(&client, path.id).await?;
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(20);
+
+    let (comments, total) =
+        services::comment::get_comments_for_post(&client, path.id, offset, limit).await?;
+    Ok(Json(ApiResponse::with_meta(comments, total, limit, offset)))
+}
+
+pub async fn create_comment(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+    Valid(Json(input)): Valid<Json<CreateCommentRequest>>,
+) -> Result<Json<ApiResponse<Comment>>, AppError> {
+    let mut client = pool.get().await?;
+    let outcome = services::comment::create_comment(
+        &mut client,
+        path.id,
+        current.user.id,
+        input.parent_id,
+        input.body.trim(),
+    )
+    .await?;
+
+    match outcome {
+        CommentOutcome::Done(comment) => Ok(Json(ApiResponse::success(comment))),
+        CommentOutcome::PostNotFound => {
+            Err(AppError::NotFound(format!("Post not found: {}", path.id)))
+        }
+        CommentOutcome::ParentNotFound(parent_id) => Err(AppError::BadRequest(format!(
+            "Cannot reply to comment {}: it does not exist on this post",
+            parent_id
+        ))),
+    }
+}
+
+pub async fn update_comment(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<CommentIdPath>,
+    Valid(Json(input)): Valid<Json<UpdateCommentRequest>>,
+) -> Result<Json<ApiResponse<Comment>>, AppError> {
+    let client = pool.get().await?;
+    ensure_comment_owner(&client, path.id, &current).await?;
+
+    let comment = services::comment::update_comment(&client, path.id, input.body.trim()).await?;
+    comment
+        .map(|c| Json(ApiResponse::success(c)))
+        .ok_or_else(|| AppError::NotFound(format!("Comment not found: {}", path.id)))
+}
+
+pub async fn delete_comment(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<CommentIdPath>,
+) -> Result<Json<ApiResponse<Comment>>, AppError> {
+    let mut client = pool.get().await?;
+    ensure_comment_owner(&client, path.id, &current).await?;
+
+    let comment = services::comment::delete_comment(&mut client, path.id).await?;
+    comment
+        .map(|c| Json(ApiResponse::success(c)))
+        .ok_or_else(|| AppError::NotFound(format!("Comment not found: {}", path.id)))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/v1/posts/{id}/comments",
+            get(get_comments).post(create_comment),
+        )
+        .route(
+            "/v1/comments/{id}",
+            patch(update_comment).delete(delete_comment),
+        )
+}
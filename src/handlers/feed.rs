@@ -0,0 +1,397 @@
+use super::post::{TAG_RE, USERNAME_RE, render_body};
+use crate::config::SiteConfig;
+use crate::error::AppError;
+use crate::models::post::{BodyFormat, OrderDirection, Post, TagMode};
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use std::fmt::Write;
+use validator::Validate;
+
+/// Syndication format served by a feed endpoint
+#[derive(Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "feed.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+/// Optional tag or author scope taken from the feed path
+#[derive(Deserialize, Validate)]
+pub struct FeedPath {
+    #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
+    tag: Option<String>,
+    #[validate(length(min = 1, max = 50), regex(path = *USERNAME_RE))]
+    username: Option<String>,
+}
+
+/// Feed-level metadata shared by every format
+struct FeedInfo {
+    title: String,
+    description: String,
+    home_url: String,
+    feed_url: String,
+}
+
+/// Escape text for use in XML element content and attribute values
+pub(super) fn escape_xml(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Control characters other than tab and newlines are invalid in XML 1.0
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format a timestamp as an HTTP-date for `Last-Modified`
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn render_rss(site: &SiteConfig, info: &FeedInfo, posts: &[Post]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<channel>
+"#,
+    );
+    let _ = writeln!(xml, "<title>{}</title>", escape_xml(&info.title));
+    let _ = writeln!(xml, "<link>{}</link>", escape_xml(&info.home_url));
+    let _ = writeln!(
+        xml,
+        "<description>{}</description>",
+        escape_xml(&info.description)
+    );
+    let _ = writeln!(
+        xml,
+        r#"<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+        escape_xml(&info.feed_url)
+    );
+    if let Some(updated) = posts.iter().map(|p| p.updated_at).max() {
+        let _ = writeln!(
+            xml,
+            "<lastBuildDate>{}</lastBuildDate>",
+            updated.to_rfc2822()
+        );
+    }
+
+    for post in posts {
+        xml.push_str("<item>\n");
+        let _ = writeln!(xml, "<title>{}</title>", escape_xml(&post.title));
+        let _ = writeln!(
+            xml,
+            "<link>{}</link>",
+            escape_xml(&site.post_url(&post.user.username, &post.slug))
+        );
+        let _ = writeln!(xml, r#"<guid isPermaLink="false">{}</guid>"#, post.id);
+        let _ = writeln!(
+            xml,
+            "<pubDate>{}</pubDate>",
+            post.published_at().to_rfc2822()
+        );
+        let _ = writeln!(
+            xml,
+            "<dc:creator>{}</dc:creator>",
+            escape_xml(&post.user.username)
+        );
+        for tag in &post.tags {
+            let _ = writeln!(xml, "<category>{}</category>", escape_xml(&tag.name));
+        }
+        // Readers treat the description as HTML either way
+        if let Some(body) = item_html(post).or(item_text(post)) {
+            let _ = writeln!(xml, "<description>{}</description>", escape_xml(body));
+        }
+        xml.push_str("</item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+fn render_atom(site: &SiteConfig, info: &FeedInfo, posts: &[Post]) -> String {
+    let updated = posts
+        .iter()
+        .map(|p| p.updated_at)
+        .max()
+        .unwrap_or_else(Utc::now);
+
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+"#,
+    );
+    let _ = writeln!(xml, "<title>{}</title>", escape_xml(&info.title));
+    if !info.description.is_empty() {
+        let _ = writeln!(
+            xml,
+            "<subtitle>{}</subtitle>",
+            escape_xml(&info.description)
+        );
+    }
+    let _ = writeln!(xml, "<id>{}</id>", escape_xml(&info.feed_url));
+    let _ = writeln!(
+        xml,
+        r#"<link rel="self" href="{}"/>"#,
+        escape_xml(&info.feed_url)
+    );
+    let _ = writeln!(
+        xml,
+        r#"<link rel="alternate" href="{}"/>"#,
+        escape_xml(&info.home_url)
+    );
+    let _ = writeln!(xml, "<updated>{}</updated>", updated.to_rfc3339());
+
+    for post in posts {
+        xml.push_str("<entry>\n");
+        let _ = writeln!(xml, "<title>{}</title>", escape_xml(&post.title));
+        let _ = writeln!(xml, "<id>urn:uuid:{}</id>", post.id);
+        let _ = writeln!(
+            xml,
+            r#"<link rel="alternate" href="{}"/>"#,
+            escape_xml(&site.post_url(&post.user.username, &post.slug))
+        );
+        let _ = writeln!(
+            xml,
+            "<published>{}</published>",
+            post.published_at().to_rfc3339()
+        );
+        let _ = writeln!(xml, "<updated>{}</updated>", post.updated_at.to_rfc3339());
+        let _ = writeln!(
+            xml,
+            "<author><name>{}</name></author>",
+            escape_xml(&post.user.username)
+        );
+        for tag in &post.tags {
+            let _ = writeln!(xml, r#"<category term="{}"/>"#, escape_xml(&tag.name));
+        }
+        // Atom distinguishes the full text (`content`) from an excerpt (`summary`)
+        if let Some(html) = item_html(post) {
+            let _ = writeln!(
+                xml,
+                r#"<content type="html">{}</content>"#,
+                escape_xml(html)
+            );
+        } else if let Some(text) = item_text(post) {
+            let _ = writeln!(
+                xml,
+                r#"<summary type="text">{}</summary>"#,
+                escape_xml(text)
+            );
+        }
+        xml.push_str("</entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// Body rendered to HTML, only loaded when feeds carry full content
+fn item_html(post: &Post) -> Option<&str> {
+    post.body.as_deref()
+}
+
+/// Plain-text excerpt
+fn item_text(post: &Post) -> Option<&str> {
+    post.excerpt.as_deref()
+}
+
+fn render_json(site: &SiteConfig, info: &FeedInfo, posts: &[Post]) -> String {
+    let items: Vec<_> = posts
+        .iter()
+        .map(|post| {
+            let mut item = json!({
+                "id": post.id,
+                "url": site.post_url(&post.user.username, &post.slug),
+                "title": post.title,
+                "date_published": post.published_at().to_rfc3339(),
+                "date_modified": post.updated_at.to_rfc3339(),
+                "authors": [{ "name": post.user.username }],
+                "tags": post.tags.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+            });
+            match item_html(post) {
+                Some(html) => item["content_html"] = json!(html),
+                None => item["content_text"] = json!(item_text(post).unwrap_or("")),
+            }
+            if let Some(photo) = &post.photo_url {
+                item["image"] = json!(photo);
+            }
+            item
+        })
+        .collect();
+
+    json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": info.title,
+        "description": info.description,
+        "home_page_url": info.home_url,
+        "feed_url": info.feed_url,
+        "items": items,
+    })
+    .to_string()
+}
+
+/// Build a feed of the latest published posts, optionally limited to one tag or author.
+///
+/// `Last-Modified` is the newest `updated_at` among all posts in scope, including
+/// ones that were deleted or unpublished since, and a matching
+/// `If-Modified-Since` is answered with `304 Not Modified`.
+async fn render_feed(
+    state: AppState,
+    path: Option<Path<FeedPath>>,
+    headers: HeaderMap,
+    format: FeedFormat,
+) -> Result<Response, AppError> {
+    let scope = path.map(|Path(p)| p);
+    if let Some(scope) = &scope {
+        scope
+            .validate()
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    }
+
+    let site = &state.config.site;
+    let full_content = state.config.feed.full_content;
+    let client = state.pool.get().await?;
+    let options = services::post::ListOptions {
+        offset: 0,
+        limit: state.config.feed.item_limit,
+        search: None,
+        order_by: Some("published_at"),
+        order_direction: Some(&OrderDirection::Desc),
+        cursor: None,
+        with_count: false,
+        full_body: full_content,
+        tags: Vec::new(),
+        tag_mode: TagMode::Any,
+        exclude_tags: Vec::new(),
+    };
+
+    let tag = scope.as_ref().and_then(|s| s.tag.as_deref());
+    let username = scope.as_ref().and_then(|s| s.username.as_deref());
+    let (mut page, title, home_url) = match (tag, username) {
+        (Some(tag), _) => (
+            services::post::get_posts_by_tag(&client, tag, options).await?,
+            format!("{} - #{}", site.title, tag),
+            site.tag_url(tag),
+        ),
+        (None, Some(username)) => (
+            services::post::get_posts_by_author(&client, username, options).await?,
+            format!("{} - {}", site.title, username),
+            site.author_url(username),
+        ),
+        (None, None) => (
+            services::post::get_all_posts(&client, options).await?,
+            site.title.clone(),
+            site.url.clone(),
+        ),
+    };
+
+    // HTTP dates have whole-second precision
+    let last_modified = services::post::get_latest_change(&client, tag, username)
+        .await?
+        .map(|t| t.trunc_subsecs(0));
+    let if_modified_since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+    if let (Some(modified), Some(since)) = (last_modified, if_modified_since)
+        && modified <= since
+    {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+
+    for post in &mut page.posts {
+        render_body(&state, post, Some(BodyFormat::Html)).await?;
+    }
+
+    let info = FeedInfo {
+        title,
+        description: site.description.clone(),
+        feed_url: format!("{}/{}", home_url, format.file_name()),
+        home_url,
+    };
+    let body = match format {
+        FeedFormat::Rss => render_rss(site, &info, &page.posts),
+        FeedFormat::Atom => render_atom(site, &info, &page.posts),
+        FeedFormat::Json => render_json(site, &info, &page.posts),
+    };
+
+    let mut response = ([(header::CONTENT_TYPE, format.content_type())], body).into_response();
+    if let Some(modified) = last_modified
+        && let Ok(value) = http_date(modified).parse()
+    {
+        response.headers_mut().insert(header::LAST_MODIFIED, value);
+    }
+    Ok(response)
+}
+
+pub async fn rss_feed(
+    State(state): State<AppState>,
+    path: Option<Path<FeedPath>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    render_feed(state, path, headers, FeedFormat::Rss).await
+}
+
+pub async fn atom_feed(
+    State(state): State<AppState>,
+    path: Option<Path<FeedPath>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    render_feed(state, path, headers, FeedFormat::Atom).await
+}
+
+pub async fn json_feed(
+    State(state): State<AppState>,
+    path: Option<Path<FeedPath>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    render_feed(state, path, headers, FeedFormat::Json).await
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/feed.xml", get(rss_feed))
+        .route("/atom.xml", get(atom_feed))
+        .route("/feed.json", get(json_feed))
+        .route("/tag/{tag}/feed.xml", get(rss_feed))
+        .route("/tag/{tag}/atom.xml", get(atom_feed))
+        .route("/tag/{tag}/feed.json", get(json_feed))
+        .route("/u/{username}/feed.xml", get(rss_feed))
+        .route("/u/{username}/atom.xml", get(atom_feed))
+        .route("/u/{username}/feed.json", get(json_feed))
+}
@@ -1,4 +1,4 @@
-use crate::database::DbPool;
+use crate::state::AppState;
 use axum::{Json, Router, routing::get};
 use serde::{Deserialize, Serialize};
 
@@ -15,7 +15,7 @@ pub async fn health() -> Json<HealthResponse> {
     })
 }
 
-pub fn routes() -> Router<DbPool> {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(health))
         .route("/health", get(health))
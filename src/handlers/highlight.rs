@@ -0,0 +1,18 @@
+use crate::markdown;
+use crate::state::AppState;
+use axum::{Router, http::header, response::IntoResponse, routing::get};
+
+/// Serve the stylesheet for the token classes in highlighted code blocks
+pub async fn highlight_css() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/css; charset=utf-8"),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        markdown::highlight_css(),
+    )
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/highlight.css", get(highlight_css))
+}
@@ -0,0 +1,288 @@
+use crate::auth::CurrentUser;
+use crate::config::MediaConfig;
+use crate::error::AppError;
+use crate::media::{self, ImageFile, ImageKind};
+use crate::models::media::Media;
+use crate::response::ApiResponse;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::Deserialize;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+/// Multipart field holding the uploaded file
+const FILE_FIELD: &str = "file";
+
+/// Room for multipart boundaries, headers and other fields around the file
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+pub struct MediaIdPath {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct MediaFilePath {
+    pub id: Uuid,
+    pub file: String,
+}
+
+fn media_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Media not found: {}", id))
+}
+
+fn bad_multipart(e: axum::extract::multipart::MultipartError) -> AppError {
+    // Bodies over `upload_body_limit` fail while being read
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return AppError::PayloadTooLarge(String::from("Upload is too large"));
+    }
+    AppError::BadRequest(format!("Invalid multipart body: {}", e))
+}
+
+fn storage_error(e: std::io::Error) -> AppError {
+    tracing::error!("Media storage error: {}", e);
+    AppError::InternalServerError(String::from("Media storage is unavailable"))
+}
+
+/// Read the `file` field of an upload, rejecting it once it exceeds `max_size`
+async fn read_file_field(multipart: &mut Multipart, max_size: usize) -> Result<Vec<u8>, AppError> {
+    while let Some(mut field) = multipart.next_field().await.map_err(bad_multipart)? {
+        if field.name() != Some(FILE_FIELD) {
+            continue;
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(bad_multipart)? {
+            if data.len() + chunk.len() > max_size {
+                return Err(AppError::PayloadTooLarge(format!(
+                    "Uploads are limited to {} bytes",
+                    max_size
+                )));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(data);
+    }
+    Err(AppError::BadRequest(format!(
+        "Missing multipart field: {}",
+        FILE_FIELD
+    )))
+}
+
+/// Body limit for upload routes: the largest accepted file plus its multipart
+/// framing. `read_file_field` checks the file itself.
+pub(super) fn upload_body_limit(config: &MediaConfig) -> DefaultBodyLimit {
+    DefaultBodyLimit::max(config.max_upload_size.saturating_add(MULTIPART_OVERHEAD))
+}
+
+/// Validate an uploaded image, store it with its WebP and resized copies and
+/// record it for `current`
+pub(super) async fn store_upload(
+    state: &AppState,
+    current: &CurrentUser,
+    mut multipart: Multipart,
+) -> Result<Media, AppError> {
+    let config = &state.config.media;
+    let data = read_file_field(&mut multipart, config.max_upload_size).await?;
+    let kind = ImageKind::sniff(&data).ok_or_else(|| {
+        AppError::UnsupportedMediaType(String::from(
+            "Only JPEG, PNG, GIF and WebP images are accepted",
+        ))
+    })?;
+
+    // Decoding needs up to a few hundred MiB, so only a few uploads are
+    // processed at once; the rest wait for a permit
+    let permit = state
+        .image_jobs
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Image processing failed: {}", e)))?;
+    // Decoding and encoding are CPU-bound, so keep them off the async workers
+    let widths = config.variant_widths.clone();
+    let files: Vec<ImageFile> = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        media::process_image(&data, kind, &widths)
+    })
+    .await
+    .map_err(|e| AppError::InternalServerError(format!("Image processing failed: {}", e)))?
+    .map_err(|e| match e {
+        // Reading from memory only fails on truncated or malformed data
+        image::ImageError::Decoding(_)
+        | image::ImageError::Limits(_)
+        | image::ImageError::Unsupported(_)
+        | image::ImageError::IoError(_) => AppError::BadRequest(format!("Invalid image: {}", e)),
+        e => AppError::InternalServerError(format!("Image processing failed: {}", e)),
+    })?;
+
+    let id = Uuid::new_v4();
+    let file_names: Vec<String> = files.iter().map(ImageFile::file_name).collect();
+    let result = save_upload(state, current, id, &files).await;
+    if result.is_err() {
+        // Nothing refers to the files without the record, so don't keep them
+        delete_files(state, id, &file_names).await;
+    }
+    result
+}
+
+async fn save_upload(
+    state: &AppState,
+    current: &CurrentUser,
+    id: Uuid,
+    files: &[ImageFile],
+) -> Result<Media, AppError> {
+    let (original, variants) = files.split_first().ok_or_else(|| {
+        AppError::InternalServerError(String::from("Image processing produced no files"))
+    })?;
+    for file in files {
+        state
+            .storage
+            .put(&format!("{}/{}", id, file.file_name()), &file.data)
+            .await
+            .map_err(storage_error)?;
+    }
+
+    let mut client = state.pool.get().await?;
+    let media = services::media::create_media(
+        &mut client,
+        &state.config.site,
+        current.user.id,
+        id,
+        original,
+        variants,
+    )
+    .await?;
+    Ok(media)
+}
+
+/// Remove the stored files of an image. Failures are only logged: the files
+/// are unreachable once the record is gone, so they are left behind.
+async fn delete_files(state: &AppState, id: Uuid, file_names: &[String]) {
+    for file_name in file_names {
+        let key = format!("{}/{}", id, file_name);
+        if let Err(e) = state.storage.delete(&key).await {
+            tracing::warn!("Failed to delete media file {}: {}", key, e);
+        }
+    }
+}
+
+/// Delete an image and its stored files, returning it, or `None` if it does
+/// not exist
+pub(super) async fn remove_media(
+    state: &AppState,
+    client: &mut Client,
+    id: Uuid,
+) -> Result<Option<Media>, AppError> {
+    let Some(media) = services::media::delete_media(client, &state.config.site, id).await? else {
+        return Ok(None);
+    };
+    let file_names: Vec<String> = media.variants.iter().map(|v| v.file_name.clone()).collect();
+    delete_files(state, id, &file_names).await;
+    Ok(Some(media))
+}
+
+/// Delete an upload that nothing refers to after a failed request. Failures
+/// are only logged, as the request already failed for another reason.
+pub(super) async fn discard_media(state: &AppState, id: Uuid) {
+    let result = match state.pool.get().await {
+        Ok(mut client) => remove_media(state, &mut client, id).await,
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to delete unused media {}: {:?}", id, e);
+    }
+}
+
+/// Upload an image as the `file` field of a multipart form
+pub async fn upload_media(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    multipart: Multipart,
+) -> Result<Json<ApiResponse<Media>>, AppError> {
+    let media = store_upload(&state, &current, multipart).await?;
+
+    Ok(Json(ApiResponse::success(media)))
+}
+
+pub async fn get_media(
+    State(state): State<AppState>,
+    Path(path): Path<MediaIdPath>,
+) -> Result<Json<ApiResponse<Media>>, AppError> {
+    let client = state.pool.get().await?;
+    let media = services::media::get_media(&client, &state.config.site, path.id)
+        .await?
+        .ok_or_else(|| media_not_found(path.id))?;
+
+    Ok(Json(ApiResponse::success(media)))
+}
+
+/// Delete an image and its files; allowed for the uploader and admins
+pub async fn delete_media(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    Path(path): Path<MediaIdPath>,
+) -> Result<Json<ApiResponse<Media>>, AppError> {
+    let mut client = state.pool.get().await?;
+    match services::media::get_media_owner(&client, path.id).await? {
+        Some(owner) if owner == current.user.id || current.is_admin() => {}
+        Some(_) => {
+            return Err(AppError::Forbidden(String::from(
+                "You can only delete your own media",
+            )));
+        }
+        None => return Err(media_not_found(path.id)),
+    }
+
+    let media = remove_media(&state, &mut client, path.id)
+        .await?
+        .ok_or_else(|| media_not_found(path.id))?;
+
+    Ok(Json(ApiResponse::success(media)))
+}
+
+/// Serve a stored file. Files never change once written, so they may be
+/// cached indefinitely.
+pub async fn serve_media_file(
+    State(state): State<AppState>,
+    Path(path): Path<MediaFilePath>,
+) -> Result<Response, AppError> {
+    let not_found = || AppError::NotFound(format!("Media file not found: {}", path.file));
+    let (name, extension) = path.file.split_once('.').ok_or_else(not_found)?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(not_found());
+    }
+    let kind = ImageKind::from_extension(extension).ok_or_else(not_found)?;
+
+    let data = state
+        .storage
+        .get(&format!("{}/{}", path.id, path.file))
+        .await
+        .map_err(storage_error)?
+        .ok_or_else(not_found)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, kind.content_type()),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+pub fn routes(config: &MediaConfig) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/v1/media",
+            post(upload_media).layer(upload_body_limit(config)),
+        )
+        .route("/v1/media/{id}", get(get_media).delete(delete_media))
+        .route("/media/{id}/{file}", get(serve_media_file))
+}
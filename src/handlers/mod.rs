@@ -1,19 +1,43 @@
+mod auth;
+mod comment;
+mod feed;
 mod health;
+mod highlight;
+mod media;
 mod post;
+mod reaction;
+mod revision;
+mod sitemap;
 mod tag;
+mod user;
 
-use crate::database::DbPool;
-use axum::Router;
+use crate::state::AppState;
+use axum::{Router, middleware};
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 
-pub fn create_router() -> Router<DbPool> {
+pub fn create_router(state: AppState) -> Router {
     Router::new()
         .merge(health::routes())
+        .merge(highlight::routes())
+        .merge(auth::routes())
+        .merge(comment::routes())
+        .merge(feed::routes())
+        .merge(media::routes(&state.config.media))
         .merge(post::routes())
+        .merge(reaction::routes())
+        .merge(revision::routes())
+        .merge(sitemap::routes())
         .merge(tag::routes())
+        .merge(user::routes(&state.config.media))
+        // Verify bearer tokens up front so handlers can rely on `CurrentUser`
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            crate::auth::authenticate,
+        ))
         // TraceLayer should be added early to trace all requests
         // It provides good defaults: logs method, uri, status, latency automatically
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
+        .with_state(state)
 }
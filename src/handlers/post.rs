@@ -1,18 +1,27 @@
+use crate::auth::{CurrentUser, Viewer};
 use crate::database::DbPool;
 use crate::error::AppError;
-use crate::models::post::{OrderDirection, Post};
+use crate::markdown;
+use crate::models::post::{BodyFormat, OrderDirection, Post, PostStatus, TagMode, TrendingWindow};
 use crate::response::ApiResponse;
 use crate::services;
+use crate::services::view::Visitor;
+use crate::state::AppState;
 use axum::{
     Json, Router,
-    extract::{Path, Query, State},
-    routing::get,
+    extract::{ConnectInfo, Path, Query, RawQuery, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
 };
 use axum_valid::Valid;
+use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
-use validator::Validate;
+use serde::{Deserialize, Deserializer};
+use std::net::{IpAddr, SocketAddr};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
 
 #[derive(Deserialize, Validate)]
 pub struct RandomPostQuery {
@@ -31,54 +40,153 @@ pub struct PaginationQuery {
     search: Option<String>,
     order_by: Option<String>,
     order_direction: Option<OrderDirection>,
+    /// Opaque keyset cursor from `meta.next_cursor` or `meta.prev_cursor`
+    #[validate(length(max = 1024))]
+    cursor: Option<String>,
+    /// Set to `false` to skip the total count query
+    with_count: Option<bool>,
+    /// Comma-separated tag names to filter by
+    #[validate(length(max = 600), custom(function = "validate_tag_list"))]
+    tags: Option<String>,
+    tag_mode: Option<TagMode>,
+    /// Comma-separated tag names to exclude
+    #[validate(length(max = 600), custom(function = "validate_tag_list"))]
+    exclude_tags: Option<String>,
 }
 
-static USERNAME_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap());
+pub(super) static USERNAME_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap());
 static SLUG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9-]+$").unwrap());
-static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap());
+pub(super) static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap());
 
-fn get_pagination_params(
+/// Split a comma-separated tag list, dropping blanks and duplicates
+fn parse_tag_list(value: Option<&str>) -> Vec<String> {
+    normalize_tags(value.map(|v| {
+        v.split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect()
+    }))
+    .unwrap_or_default()
+}
+
+/// Validate a comma-separated tag list like a request's `tags` array
+fn validate_tag_list(value: &str) -> Result<(), ValidationError> {
+    let tags = parse_tag_list(Some(value));
+    if tags.len() > 10 {
+        return Err(ValidationError::new("too_many_tags"));
+    }
+    validate_tag_names(&tags)
+}
+
+pub(super) fn get_pagination_params(
     query: &PaginationQuery,
-) -> (
-    i64,
-    i64,
-    Option<&str>,
-    Option<&str>,
-    Option<&OrderDirection>,
-) {
-    let offset = query.offset.unwrap_or(0);
-    let limit = query.limit.unwrap_or(10);
-    let search = query.search.as_deref();
+) -> Result<services::post::ListOptions<'_>, AppError> {
+    let search = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty());
     let order_by = query.order_by.as_deref();
     let order_direction = query.order_direction.as_ref();
-    (offset, limit, search, order_by, order_direction)
+    let cursor = query
+        .cursor
+        .as_deref()
+        .map(|c| {
+            services::post::decode_cursor(c, order_by, order_direction, search.is_some())
+                .ok_or_else(|| {
+                    AppError::BadRequest(String::from(
+                        "Invalid cursor or cursor does not match the requested ordering",
+                    ))
+                })
+        })
+        .transpose()?;
+
+    Ok(services::post::ListOptions {
+        offset: query.offset.unwrap_or(0),
+        limit: query.limit.unwrap_or(10),
+        search,
+        order_by,
+        order_direction,
+        cursor,
+        with_count: query.with_count.unwrap_or(true),
+        full_body: false,
+        tags: parse_tag_list(query.tags.as_deref()),
+        tag_mode: query.tag_mode.unwrap_or_default(),
+        exclude_tags: parse_tag_list(query.exclude_tags.as_deref()),
+    })
+}
+
+/// Wrap a page of posts in the standard envelope
+pub(super) fn page_response(
+    page: services::post::PostPage,
+    limit: i64,
+    offset: i64,
+) -> Json<ApiResponse<Vec<Post>>> {
+    Json(ApiResponse::with_cursors(
+        page.posts,
+        page.total,
+        limit,
+        offset,
+        page.next_cursor,
+        page.prev_cursor,
+    ))
 }
 
 pub async fn get_posts(
     State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
     Valid(query): Valid<Query<PaginationQuery>>,
 ) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
     let client = pool.get().await?;
-    let (offset, limit, search, order_by, order_direction) = get_pagination_params(&query);
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
 
-    let (posts, total) =
-        services::post::get_all_posts(&client, offset, limit, search, order_by, order_direction)
-            .await?;
+    let mut page = services::post::get_all_posts(&client, options).await?;
+    services::reaction::fill_viewer_flags(&client, viewer, &mut page.posts).await?;
 
-    Ok(Json(ApiResponse::with_meta(posts, total, limit, offset)))
+    Ok(page_response(page, limit, offset))
 }
 
 pub async fn get_random_posts(
     State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
     Valid(query): Valid<Query<RandomPostQuery>>,
 ) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
     let client = pool.get().await?;
     let limit = query.limit.unwrap_or(6);
-    let posts = services::post::get_random_posts(&client, limit).await?;
+    let mut posts = services::post::get_random_posts(&client, limit).await?;
+    services::reaction::fill_viewer_flags(&client, viewer, &mut posts).await?;
     let total = posts.len() as i64;
     Ok(Json(ApiResponse::with_meta(posts, total, limit, 0)))
 }
 
+#[derive(Deserialize, Validate)]
+pub struct TrendingPostQuery {
+    window: Option<TrendingWindow>,
+    #[validate(range(min = 0, max = 1000))]
+    offset: Option<i64>,
+    #[validate(range(min = 1, max = 100))]
+    limit: Option<i64>,
+}
+
+pub async fn get_trending_posts(
+    State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
+    Valid(query): Valid<Query<TrendingPostQuery>>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    let client = pool.get().await?;
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(10);
+    let window = query.window.unwrap_or_default();
+
+    let (mut posts, total) =
+        services::post::get_trending_posts(&client, window, offset, limit).await?;
+    services::reaction::fill_viewer_flags(&client, viewer, &mut posts).await?;
+
+    Ok(Json(ApiResponse::with_meta(posts, total, limit, offset)))
+}
+
 #[derive(Deserialize, Validate)]
 pub struct TagPath {
     #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
@@ -87,24 +195,18 @@ pub struct TagPath {
 
 pub async fn get_posts_by_tag(
     State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
     Valid(Path(tag_path)): Valid<Path<TagPath>>,
     Valid(query): Valid<Query<PaginationQuery>>,
 ) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
     let client = pool.get().await?;
-    let (offset, limit, search, order_by, order_direction) = get_pagination_params(&query);
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
 
-    let (posts, total) = services::post::get_posts_by_tag(
-        &client,
-        &tag_path.tag,
-        offset,
-        limit,
-        search,
-        order_by,
-        order_direction,
-    )
-    .await?;
+    let mut page = services::post::get_posts_by_tag(&client, &tag_path.tag, options).await?;
+    services::reaction::fill_viewer_flags(&client, viewer, &mut page.posts).await?;
 
-    Ok(Json(ApiResponse::with_meta(posts, total, limit, offset)))
+    Ok(page_response(page, limit, offset))
 }
 
 #[derive(Deserialize, Validate)]
@@ -115,30 +217,568 @@ pub struct PostPath {
     pub slug: String,
 }
 
+#[derive(Deserialize)]
+pub struct BodyFormatQuery {
+    pub format: Option<BodyFormat>,
+}
+
+/// Replace the Markdown body of `post` with the requested representation and
+/// add its table of contents and length, reusing output cached for the same
+/// `updated_at`
+pub(super) async fn render_body(
+    state: &AppState,
+    post: &mut Post,
+    format: Option<BodyFormat>,
+) -> Result<(), AppError> {
+    let format = format.unwrap_or_default();
+    let Some(body) = post.body.take() else {
+        return Ok(());
+    };
+
+    let key = (post.id, post.updated_at, format);
+    let rendered = match state.rendered.get(&key) {
+        Some(rendered) => rendered,
+        None => {
+            // Highlighting and sanitizing a long body takes a while, so keep
+            // it off the async workers
+            let rendered = tokio::task::spawn_blocking(move || markdown::render(&body, format))
+                .await
+                .map_err(|e| AppError::InternalServerError(format!("Rendering failed: {}", e)))?;
+            state.rendered.insert(key, rendered.clone());
+            rendered
+        }
+    };
+    post.reading_time_minutes = Some(rendered.reading_time_minutes());
+    post.word_count = Some(rendered.word_count);
+    post.toc = Some(rendered.toc);
+    post.body = Some(rendered.body);
+    Ok(())
+}
+
+/// Identify a visitor for view deduplication: the user id when signed in,
+/// otherwise the client IP together with the user agent.
+///
+/// Behind a trusted proxy the client IP is the rightmost `X-Forwarded-For`
+/// entry, the one the proxy appended; entries to its left are written by the
+/// client and can be anything.
+fn visitor(
+    viewer: Option<Uuid>,
+    headers: &HeaderMap,
+    addr: SocketAddr,
+    trust_proxy_headers: bool,
+) -> Visitor {
+    if let Some(id) = viewer {
+        return Visitor::User(id);
+    }
+
+    let ip = trust_proxy_headers
+        .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+        .flatten()
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+        .unwrap_or_else(|| addr.ip());
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    Visitor::Anonymous {
+        ip: ip.to_string(),
+        user_agent: user_agent.to_string(),
+    }
+}
+
 pub async fn get_post_by_username_and_slug(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
+    Viewer(viewer): Viewer,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Valid(Path(params)): Valid<Path<PostPath>>,
-) -> Result<Json<ApiResponse<Post>>, AppError> {
-    let client = pool.get().await?;
-    match services::post::get_post_by_username_and_slug(&client, &params.username, &params.slug)
-        .await
+    Query(query): Query<BodyFormatQuery>,
+    RawQuery(raw_query): RawQuery,
+) -> Result<Response, AppError> {
+    let client = state.pool.get().await?;
+    match services::post::get_post_by_username_and_slug(
+        &client,
+        &params.username,
+        &params.slug,
+        viewer,
+    )
+    .await
     {
-        Ok(Some(post)) => Ok(Json(ApiResponse::success(post))),
-        Ok(None) => Err(AppError::NotFound(format!(
-            "Post not found: {} by {}",
-            params.slug, params.username
-        ))),
+        Ok(Some(mut post)) => {
+            // Authors previewing their own unpublished posts are not readers
+            if post.status.is_public() {
+                let visitor = visitor(viewer, &headers, addr, state.config.trust_proxy_headers);
+                state.views.record(post.id, &visitor);
+            }
+            services::reaction::fill_viewer_flags(&client, viewer, std::slice::from_mut(&mut post))
+                .await?;
+            render_body(&state, &mut post, query.format).await?;
+            Ok(Json(ApiResponse::success(post)).into_response())
+        }
+        Ok(None) => {
+            // Old links keep working after the author or slug is renamed
+            let moved =
+                services::post::resolve_moved_post(&client, &params.username, &params.slug, viewer)
+                    .await?;
+            match moved {
+                Some((username, slug)) if (username != params.username || slug != params.slug) => {
+                    let mut location = format!("/v1/posts/u/{}/{}", username, slug);
+                    if let Some(raw_query) = raw_query {
+                        location.push('?');
+                        location.push_str(&raw_query);
+                    }
+                    Ok((
+                        StatusCode::MOVED_PERMANENTLY,
+                        [(header::LOCATION, location)],
+                    )
+                        .into_response())
+                }
+                _ => Err(AppError::NotFound(format!(
+                    "Post not found: {} by {}",
+                    params.slug, params.username
+                ))),
+            }
+        }
         Err(e) => Err(AppError::from(e)),
     }
 }
 
-pub fn routes() -> Router<DbPool> {
+#[derive(Deserialize)]
+pub struct OwnPostsQuery {
+    status: Option<PostStatus>,
+}
+
+pub async fn get_my_posts(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(query): Valid<Query<PaginationQuery>>,
+    Query(filter): Query<OwnPostsQuery>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    let client = pool.get().await?;
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
+
+    let mut page =
+        services::post::get_own_posts(&client, current.user.id, filter.status, options).await?;
+    services::reaction::fill_viewer_flags(&client, Some(current.user.id), &mut page.posts).await?;
+
+    Ok(page_response(page, limit, offset))
+}
+
+pub async fn get_my_bookmarks(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(query): Valid<Query<PaginationQuery>>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    let client = pool.get().await?;
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
+
+    let mut page = services::post::get_bookmarked_posts(&client, current.user.id, options).await?;
+    services::reaction::fill_viewer_flags(&client, Some(current.user.id), &mut page.posts).await?;
+
+    Ok(page_response(page, limit, offset))
+}
+
+pub async fn get_my_trash(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(query): Valid<Query<PaginationQuery>>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    let client = pool.get().await?;
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
+
+    let page = services::post::get_trashed_posts(&client, Some(current.user.id), options).await?;
+
+    Ok(page_response(page, limit, offset))
+}
+
+/// List deleted posts of every author (admin only)
+pub async fn get_trash(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(query): Valid<Query<PaginationQuery>>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    if !current.is_admin() {
+        return Err(AppError::Forbidden(String::from(
+            "Only admins can view every deleted post",
+        )));
+    }
+    let client = pool.get().await?;
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
+
+    let page = services::post::get_trashed_posts(&client, None, options).await?;
+
+    Ok(page_response(page, limit, offset))
+}
+
+/// Number of related posts computed and cached per post
+const MAX_RELATED_POSTS: i64 = 20;
+
+#[derive(Deserialize, Validate)]
+pub struct RelatedPostQuery {
+    #[validate(range(min = 1, max = 20))]
+    limit: Option<i64>,
+}
+
+pub async fn get_related_posts(
+    State(state): State<AppState>,
+    Viewer(viewer): Viewer,
+    Path(path): Path<PostIdPath>,
+    Valid(Query(query)): Valid<Query<RelatedPostQuery>>,
+) -> Result<Json<ApiResponse<Vec<Post>>>, AppError> {
+    let client = state.pool.get().await?;
+    if !services::post::post_is_visible(&client, path.id).await? {
+        return Err(AppError::NotFound(format!("Post not found: {}", path.id)));
+    }
+
+    let mut posts = match state.related.get(&path.id) {
+        // Posts may have been unpublished or deleted since the list was cached
+        Some(mut posts) => {
+            services::post::retain_published(&client, &mut posts).await?;
+            posts
+        }
+        None => {
+            let posts = services::post::get_related_posts(
+                &client,
+                path.id,
+                MAX_RELATED_POSTS,
+                state.config.related.title_similarity,
+            )
+            .await?;
+            state.related.insert(path.id, posts.clone());
+            posts
+        }
+    };
+    let limit = query.limit.unwrap_or(5);
+    posts.truncate(limit as usize);
+    services::reaction::fill_viewer_flags(&client, viewer, &mut posts).await?;
+
+    let total = posts.len() as i64;
+    Ok(Json(ApiResponse::with_meta(posts, total, limit, 0)))
+}
+
+/// Validate every tag name against `TAG_RE` and the tag length limits
+fn validate_tag_names(tags: &[String]) -> Result<(), ValidationError> {
+    let valid = tags
+        .iter()
+        .all(|t| (1..=50).contains(&t.chars().count()) && TAG_RE.is_match(t));
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("tag_name"))
+    }
+}
+
+/// Reject titles that are empty once trimmed, as they are stored trimmed
+fn validate_title(title: &str) -> Result<(), ValidationError> {
+    if title.trim().is_empty() {
+        Err(ValidationError::new("blank"))
+    } else {
+        Ok(())
+    }
+}
+
+/// `publishAt` is required for, and only accepted with, a scheduled status,
+/// and must lie in the future
+fn validate_schedule(
+    status: Option<PostStatus>,
+    publish_at: Option<DateTime<Utc>>,
+) -> Result<(), ValidationError> {
+    match (status, publish_at) {
+        (Some(PostStatus::Scheduled), None) => Err(ValidationError::new("publish_at_required")),
+        (Some(PostStatus::Scheduled), Some(at)) if at <= Utc::now() => {
+            Err(ValidationError::new("publish_at_in_past"))
+        }
+        (Some(PostStatus::Scheduled), Some(_)) | (_, None) => Ok(()),
+        (_, Some(_)) => Err(ValidationError::new("publish_at_requires_scheduled")),
+    }
+}
+
+fn validate_create_schedule(input: &CreatePostRequest) -> Result<(), ValidationError> {
+    validate_schedule(input.status, input.publish_at)
+}
+
+fn validate_update_schedule(input: &UpdatePostRequest) -> Result<(), ValidationError> {
+    validate_schedule(input.status, input.publish_at)
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_create_schedule"))]
+pub struct CreatePostRequest {
+    #[validate(length(max = 255), custom(function = "validate_title"))]
+    title: String,
+    #[validate(length(max = 100_000))]
+    body: Option<String>,
+    #[validate(length(min = 1, max = 100), regex(path = *SLUG_RE))]
+    slug: Option<String>,
+    #[validate(url, length(max = 2048))]
+    photo_url: Option<String>,
+    /// Defaults to draft
+    status: Option<PostStatus>,
+    publish_at: Option<DateTime<Utc>>,
+    #[validate(length(max = 10), custom(function = "validate_tag_names"))]
+    tags: Option<Vec<String>>,
+    /// Summary shown in listings; generated from the body when blank
+    #[validate(length(max = 300))]
+    excerpt: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_update_schedule"))]
+pub struct UpdatePostRequest {
+    #[validate(length(max = 255), custom(function = "validate_title"))]
+    title: Option<String>,
+    /// `null` removes the body
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(max = 100_000))]
+    body: Option<Option<String>>,
+    #[validate(length(min = 1, max = 100), regex(path = *SLUG_RE))]
+    slug: Option<String>,
+    /// `null` removes the photo
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(url, length(max = 2048))]
+    photo_url: Option<Option<String>>,
+    status: Option<PostStatus>,
+    publish_at: Option<DateTime<Utc>>,
+    #[validate(length(max = 10), custom(function = "validate_tag_names"))]
+    tags: Option<Vec<String>>,
+    /// Summary shown in listings; generated from the body when blank
+    #[validate(length(max = 300))]
+    excerpt: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PostIdPath {
+    pub id: Uuid,
+}
+
+/// Tell an explicit `null` (`Some(None)`) apart from a missing field (`None`,
+/// via `#[serde(default)]`) so PATCH requests can clear nullable columns
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Trim and de-duplicate tag names, preserving their first occurrence
+fn normalize_tags(tags: Option<Vec<String>>) -> Option<Vec<String>> {
+    tags.map(|tags| {
+        let mut seen = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = tag.trim().to_string();
+            if !seen.contains(&tag) {
+                seen.push(tag);
+            }
+        }
+        seen
+    })
+}
+
+/// Ensure the post exists and is owned by the caller
+pub(super) async fn ensure_post_owner(
+    client: &tokio_postgres::Client,
+    id: Uuid,
+    current: &CurrentUser,
+) -> Result<(), AppError> {
+    match services::post::get_post_owner(client, id).await? {
+        Some(owner) if owner == current.user.id => Ok(()),
+        Some(_) => Err(AppError::Forbidden(String::from(
+            "You can only modify your own posts",
+        ))),
+        None => Err(AppError::NotFound(format!("Post not found: {}", id))),
+    }
+}
+
+pub async fn create_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Json(input)): Valid<Json<CreatePostRequest>>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let mut client = pool.get().await?;
+    let tags = normalize_tags(input.tags).unwrap_or_default();
+
+    let post = services::post::create_post(
+        &mut client,
+        current.user.id,
+        services::post::NewPost {
+            title: input.title.trim(),
+            body: input.body.as_deref(),
+            slug: input.slug.as_deref(),
+            photo_url: input.photo_url.as_deref(),
+            status: input.status.unwrap_or(PostStatus::Draft),
+            publish_at: input.publish_at,
+            tags: &tags,
+            excerpt: input.excerpt.as_deref(),
+        },
+    )
+    .await?;
+
+    Ok(Json(ApiResponse::success(post)))
+}
+
+pub async fn update_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+    Valid(Json(input)): Valid<Json<UpdatePostRequest>>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let mut client = pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+    let tags = normalize_tags(input.tags);
+
+    let post = services::post::update_post(
+        &mut client,
+        current.user.id,
+        path.id,
+        services::post::PostChanges {
+            title: input.title.as_deref().map(str::trim),
+            body: input.body.as_ref().map(Option::as_deref),
+            slug: input.slug.as_deref(),
+            photo_url: input.photo_url.as_ref().map(Option::as_deref),
+            status: input.status,
+            publish_at: input.publish_at,
+            tags: tags.as_deref(),
+            excerpt: input.excerpt.as_deref(),
+        },
+    )
+    .await?;
+
+    post.map(|p| Json(ApiResponse::success(p)))
+        .ok_or_else(|| AppError::NotFound(format!("Post not found: {}", path.id)))
+}
+
+pub async fn delete_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let client = pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+    let post = services::post::delete_post(&client, current.user.id, path.id).await?;
+
+    post.map(|p| Json(ApiResponse::success(p)))
+        .ok_or_else(|| AppError::NotFound(format!("Post not found: {}", path.id)))
+}
+
+/// Move a deleted post out of the trash; allowed for its author and admins
+pub async fn restore_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let client = pool.get().await?;
+    match services::post::get_trashed_post_owner(&client, path.id).await? {
+        Some(owner) if owner == current.user.id || current.is_admin() => {}
+        Some(_) => {
+            return Err(AppError::Forbidden(String::from(
+                "You can only restore your own posts",
+            )));
+        }
+        None => {
+            return Err(AppError::NotFound(format!(
+                "Deleted post not found: {}",
+                path.id
+            )));
+        }
+    }
+    let post = services::post::restore_post(&client, path.id).await?;
+
+    post.map(|p| Json(ApiResponse::success(p)))
+        .ok_or_else(|| AppError::NotFound(format!("Deleted post not found: {}", path.id)))
+}
+
+pub fn routes() -> Router<AppState> {
     Router::new()
-        .route("/v1/posts", get(get_posts))
+        .route("/v1/posts", get(get_posts).post(create_post))
+        .route("/v1/posts/{id}", patch(update_post).delete(delete_post))
+        .route("/v1/me/posts", get(get_my_posts))
+        .route("/v1/me/bookmarks", get(get_my_bookmarks))
+        .route("/v1/me/trash", get(get_my_trash))
+        .route("/v1/trash", get(get_trash))
+        .route("/v1/posts/{id}/restore", post(restore_post))
         .route("/v1/posts/random", get(get_random_posts))
+        .route("/v1/posts/trending", get(get_trending_posts))
+        .route("/v1/posts/{id}/related", get(get_related_posts))
         .route("/v1/posts/tag/{tag}", get(get_posts_by_tag))
         .route(
             "/v1/posts/u/{username}/{slug}",
             get(get_post_by_username_and_slug),
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn rejects_blank_titles() {
+        let create: CreatePostRequest =
+            serde_json::from_value(json!({ "title": "  \t " })).unwrap();
+        assert!(create.validate().is_err());
+        let update: UpdatePostRequest = serde_json::from_value(json!({ "title": "   " })).unwrap();
+        assert!(update.validate().is_err());
+
+        let create: CreatePostRequest = serde_json::from_value(json!({ "title": " Hi " })).unwrap();
+        assert!(create.validate().is_ok());
+        let update: UpdatePostRequest = serde_json::from_value(json!({})).unwrap();
+        assert!(update.validate().is_ok());
+    }
+
+    #[test]
+    fn takes_the_client_ip_from_the_rightmost_forwarded_hop() {
+        let addr: SocketAddr = "10.0.0.9:443".parse().unwrap();
+        let ip_of = |forwarded: &str, trusted: bool| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", forwarded.parse().unwrap());
+            match visitor(None, &headers, addr, trusted) {
+                Visitor::Anonymous { ip, .. } => ip,
+                Visitor::User(_) => unreachable!(),
+            }
+        };
+
+        assert_eq!(ip_of("6.6.6.6, 203.0.113.7", true), "203.0.113.7");
+        assert_eq!(ip_of("spoofed-1, 203.0.113.7", true), "203.0.113.7");
+        assert_eq!(ip_of("203.0.113.7", true), "203.0.113.7");
+        assert_eq!(ip_of("203.0.113.7, garbage", true), "10.0.0.9");
+        assert_eq!(ip_of("203.0.113.7", false), "10.0.0.9");
+    }
+
+    #[test]
+    fn null_clears_body_and_photo_but_missing_leaves_them() {
+        let update: UpdatePostRequest =
+            serde_json::from_value(json!({ "body": null, "photoUrl": null })).unwrap();
+        assert_eq!(update.body, Some(None));
+        assert_eq!(update.photo_url, Some(None));
+        assert!(update.validate().is_ok());
+
+        let update: UpdatePostRequest = serde_json::from_value(json!({})).unwrap();
+        assert_eq!(update.body, None);
+        assert_eq!(update.photo_url, None);
+
+        let update: UpdatePostRequest =
+            serde_json::from_value(json!({ "photoUrl": "not a url" })).unwrap();
+        assert!(update.validate().is_err());
+    }
+
+    #[test]
+    fn malformed_cursors_are_bad_requests() {
+        for cursor in ["garbage", "eyJmIjoiaWQifQ", "!!!"] {
+            let query: PaginationQuery =
+                serde_json::from_value(json!({ "cursor": cursor })).unwrap();
+            assert!(
+                matches!(get_pagination_params(&query), Err(AppError::BadRequest(_))),
+                "{} accepted",
+                cursor
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use crate::auth::CurrentUser;
+use crate::database::DbPool;
+use crate::error::AppError;
+use crate::models::post::Post;
+use crate::response::ApiResponse;
+use crate::services;
+use crate::services::reaction::Reaction;
+use crate::state::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    routing::put,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct PostIdPath {
+    pub id: Uuid,
+}
+
+/// Apply a reaction and return the post with updated counters and viewer flags.
+///
+/// Reactions can only be added to visible posts, but can always be removed, so
+/// readers can clean up after a post is unpublished or deleted. `data` is then
+/// `null`, whether or not the post exists.
+async fn react(
+    pool: DbPool,
+    current: CurrentUser,
+    post_id: Uuid,
+    reaction: Reaction,
+    active: bool,
+) -> Result<Json<ApiResponse<Option<Post>>>, AppError> {
+    let mut client = pool.get().await?;
+    let visible = services::post::post_is_visible(&client, post_id).await?;
+    if active && !visible {
+        return Err(AppError::NotFound(format!("Post not found: {}", post_id)));
+    }
+
+    services::reaction::set_reaction(&mut client, reaction, post_id, current.user.id, active)
+        .await?;
+    if !visible {
+        return Ok(Json(ApiResponse::success(None)));
+    }
+
+    let mut post = services::post::get_post_by_id(&client, post_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Post not found: {}", post_id)))?;
+    services::reaction::fill_viewer_flags(
+        &client,
+        Some(current.user.id),
+        std::slice::from_mut(&mut post),
+    )
+    .await?;
+
+    Ok(Json(ApiResponse::success(Some(post))))
+}
+
+pub async fn like_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Option<Post>>>, AppError> {
+    react(pool, current, path.id, Reaction::Like, true).await
+}
+
+pub async fn unlike_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Option<Post>>>, AppError> {
+    react(pool, current, path.id, Reaction::Like, false).await
+}
+
+pub async fn bookmark_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Option<Post>>>, AppError> {
+    react(pool, current, path.id, Reaction::Bookmark, true).await
+}
+
+pub async fn unbookmark_post(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Option<Post>>>, AppError> {
+    react(pool, current, path.id, Reaction::Bookmark, false).await
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/v1/posts/{id}/like", put(like_post).delete(unlike_post))
+        .route(
+            "/v1/posts/{id}/bookmark",
+            put(bookmark_post).delete(unbookmark_post),
+        )
+}
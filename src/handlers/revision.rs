@@ -0,0 +1,125 @@
+use super::post::{BodyFormatQuery, ensure_post_owner, render_body};
+use crate::auth::CurrentUser;
+use crate::database::DbPool;
+use crate::error::AppError;
+use crate::models::post::Post;
+use crate::models::revision::{PostRevision, RevisionDiff};
+use crate::response::ApiResponse;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    routing::{get, post},
+};
+use axum_valid::Valid;
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Deserialize)]
+pub struct PostIdPath {
+    pub id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct RevisionPath {
+    pub id: Uuid,
+    pub revision: i32,
+}
+
+fn revision_not_found(revision: i32) -> AppError {
+    AppError::NotFound(format!("Revision not found: {}", revision))
+}
+
+pub async fn get_revisions(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+) -> Result<Json<ApiResponse<Vec<PostRevision>>>, AppError> {
+    let client = pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+    let revisions = services::revision::get_revisions(&client, path.id).await?;
+
+    Ok(Json(ApiResponse::success(revisions)))
+}
+
+/// Show the post as it was at one revision
+pub async fn get_revision(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    Path(path): Path<RevisionPath>,
+    Query(query): Query<BodyFormatQuery>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let client = state.pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+    let mut post = services::revision::get_post_at_revision(&client, path.id, path.revision)
+        .await?
+        .ok_or_else(|| revision_not_found(path.revision))?;
+    render_body(&state, &mut post, query.format).await?;
+
+    Ok(Json(ApiResponse::success(post)))
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RevisionDiffQuery {
+    #[validate(range(min = 1))]
+    from: i32,
+    #[validate(range(min = 1))]
+    to: i32,
+    /// Unchanged lines shown around each hunk of the unified diff
+    #[validate(range(min = 0, max = 100))]
+    context: Option<usize>,
+}
+
+pub async fn diff_revisions(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<PostIdPath>,
+    Valid(Query(query)): Valid<Query<RevisionDiffQuery>>,
+) -> Result<Json<ApiResponse<RevisionDiff>>, AppError> {
+    let client = pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+
+    let from = services::revision::get_revision(&client, path.id, query.from)
+        .await?
+        .ok_or_else(|| revision_not_found(query.from))?;
+    let to = services::revision::get_revision(&client, path.id, query.to)
+        .await?
+        .ok_or_else(|| revision_not_found(query.to))?;
+    let context = query.context.unwrap_or(3);
+    let diff = tokio::task::spawn_blocking(move || {
+        services::revision::diff_revisions(&from, &to, context)
+    })
+    .await
+    .map_err(|e| AppError::InternalServerError(format!("Diffing failed: {}", e)))?;
+
+    Ok(Json(ApiResponse::success(diff)))
+}
+
+/// Bring back the title, body and tags of an earlier revision
+pub async fn restore_revision(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Path(path): Path<RevisionPath>,
+) -> Result<Json<ApiResponse<Post>>, AppError> {
+    let mut client = pool.get().await?;
+    ensure_post_owner(&client, path.id, &current).await?;
+    let post =
+        services::revision::restore_revision(&mut client, current.user.id, path.id, path.revision)
+            .await?;
+
+    post.map(|p| Json(ApiResponse::success(p)))
+        .ok_or_else(|| revision_not_found(path.revision))
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/v1/posts/{id}/revisions", get(get_revisions))
+        .route("/v1/posts/{id}/revisions/diff", get(diff_revisions))
+        .route("/v1/posts/{id}/revisions/{revision}", get(get_revision))
+        .route(
+            "/v1/posts/{id}/revisions/{revision}/restore",
+            post(restore_revision),
+        )
+}
@@ -0,0 +1,191 @@
+use super::feed::escape_xml;
+use crate::config::SiteConfig;
+use crate::error::AppError;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    routing::get,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt::Write;
+
+/// Maximum URLs per sitemap file allowed by the sitemap protocol
+const SITEMAP_MAX_URLS: i64 = 50_000;
+
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+/// Sections a large sitemap is split into
+#[derive(Clone, Copy)]
+enum Section {
+    Posts,
+    Tags,
+}
+
+impl Section {
+    fn name(self) -> &'static str {
+        match self {
+            Section::Posts => "posts",
+            Section::Tags => "tags",
+        }
+    }
+}
+
+/// Incrementally built `<urlset>` document
+struct UrlSet(String);
+
+impl UrlSet {
+    fn new() -> Self {
+        Self(String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+        ))
+    }
+
+    fn push(&mut self, loc: &str, lastmod: Option<DateTime<Utc>>) {
+        let _ = write!(self.0, "<url><loc>{}</loc>", escape_xml(loc));
+        if let Some(lastmod) = lastmod {
+            let _ = write!(self.0, "<lastmod>{}</lastmod>", lastmod.to_rfc3339());
+        }
+        self.0.push_str("</url>\n");
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push_str("</urlset>\n");
+        self.0
+    }
+}
+
+/// Append one page of a section to `urls`
+async fn push_section(
+    client: &tokio_postgres::Client,
+    site: &SiteConfig,
+    urls: &mut UrlSet,
+    section: Section,
+    offset: i64,
+    limit: i64,
+) -> Result<(), AppError> {
+    match section {
+        Section::Posts => {
+            for post in services::post::get_post_locations(client, offset, limit).await? {
+                urls.push(
+                    &site.post_url(&post.username, &post.slug),
+                    Some(post.updated_at),
+                );
+            }
+        }
+        Section::Tags => {
+            for name in services::tag::get_used_tag_names(client, offset, limit).await? {
+                urls.push(&site.tag_url(&name), None);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Serve every published post and every tag on one, or a sitemap index once they exceed the
+/// 50,000 URL limit of a single sitemap
+pub async fn sitemap(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let site = &state.config.site;
+    let client = state.pool.get().await?;
+    let post_count = services::post::count_published_posts(&client).await?;
+    let tag_count = services::tag::count_used_tags(&client).await?;
+
+    let body = if post_count + tag_count <= SITEMAP_MAX_URLS {
+        let mut urls = UrlSet::new();
+        push_section(&client, site, &mut urls, Section::Posts, 0, post_count).await?;
+        push_section(&client, site, &mut urls, Section::Tags, 0, tag_count).await?;
+        urls.finish()
+    } else {
+        let mut xml = String::from(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+"#,
+        );
+        for (section, count) in [(Section::Posts, post_count), (Section::Tags, tag_count)] {
+            let pages = (count + SITEMAP_MAX_URLS - 1) / SITEMAP_MAX_URLS;
+            for page in 1..=pages {
+                let loc = format!("{}/sitemaps/{}/{}.xml", site.url, section.name(), page);
+                let _ = writeln!(xml, "<sitemap><loc>{}</loc></sitemap>", escape_xml(&loc));
+            }
+        }
+        xml.push_str("</sitemapindex>\n");
+        xml
+    };
+
+    Ok(([(header::CONTENT_TYPE, XML_CONTENT_TYPE)], body))
+}
+
+#[derive(Deserialize)]
+pub struct SitemapPagePath {
+    section: String,
+    /// Page file name such as `1.xml`
+    page: String,
+}
+
+/// Serve one page of a split sitemap listed in the sitemap index
+pub async fn sitemap_page(
+    State(state): State<AppState>,
+    Path(path): Path<SitemapPagePath>,
+) -> Result<impl IntoResponse, AppError> {
+    let not_found = || AppError::NotFound(format!("Sitemap not found: {}", path.page));
+    let section = match path.section.as_str() {
+        "posts" => Section::Posts,
+        "tags" => Section::Tags,
+        _ => return Err(not_found()),
+    };
+    let page: i64 = path
+        .page
+        .strip_suffix(".xml")
+        .and_then(|n| n.parse().ok())
+        .filter(|n| (1..=10_000).contains(n))
+        .ok_or_else(not_found)?;
+
+    let client = state.pool.get().await?;
+    let mut urls = UrlSet::new();
+    push_section(
+        &client,
+        &state.config.site,
+        &mut urls,
+        section,
+        (page - 1) * SITEMAP_MAX_URLS,
+        SITEMAP_MAX_URLS,
+    )
+    .await?;
+
+    Ok(([(header::CONTENT_TYPE, XML_CONTENT_TYPE)], urls.finish()))
+}
+
+/// Serve the configured robots.txt, or allow everything except
+/// `ROBOTS_DISALLOW` paths and point crawlers at the sitemap
+pub async fn robots(State(state): State<AppState>) -> impl IntoResponse {
+    let site = &state.config.site;
+    let body = match &site.robots_txt {
+        Some(contents) => contents.clone(),
+        None => {
+            let mut body = String::from("User-agent: *\n");
+            if site.robots_disallow.is_empty() {
+                body.push_str("Disallow:\n");
+            }
+            for path in &site.robots_disallow {
+                let _ = writeln!(body, "Disallow: {}", path);
+            }
+            let _ = writeln!(body, "\nSitemap: {}/sitemap.xml", site.url);
+            body
+        }
+    };
+
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body)
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/sitemap.xml", get(sitemap))
+        .route("/sitemaps/{section}/{page}", get(sitemap_page))
+        .route("/robots.txt", get(robots))
+}
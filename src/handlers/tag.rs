@@ -1,12 +1,16 @@
+use super::post::TAG_RE;
+use crate::auth::CurrentUser;
 use crate::database::DbPool;
 use crate::error::AppError;
-use crate::models::tag::Tag;
+use crate::models::tag::{Tag, TagSort};
 use crate::response::ApiResponse;
 use crate::services;
+use crate::services::tag::TagOutcome;
+use crate::state::AppState;
 use axum::{
     Json, Router,
-    extract::{Query, State},
-    routing::get,
+    extract::{Path, Query, State},
+    routing::{get, patch, post},
 };
 use axum_valid::Valid;
 use serde::Deserialize;
@@ -19,6 +23,10 @@ pub struct TagPaginationQuery {
     offset: Option<i64>,
     #[validate(range(min = 1, max = 100))]
     limit: Option<i64>,
+    sort: Option<TagSort>,
+    /// Case-insensitive name prefix for autocomplete
+    #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
+    q: Option<String>,
 }
 
 pub async fn get_tags(
@@ -29,10 +37,122 @@ pub async fn get_tags(
     let offset = query.offset.unwrap_or(0);
     let limit = query.limit.unwrap_or(50);
 
-    let (tags, total) = services::tag::get_all_tags(&client, offset, limit).await?;
+    let (tags, total) = services::tag::get_all_tags(
+        &client,
+        offset,
+        limit,
+        query.sort.unwrap_or_default(),
+        query.q.as_deref(),
+    )
+    .await?;
     Ok(Json(ApiResponse::with_meta(tags, total, limit, offset)))
 }
 
-pub fn routes() -> Router<DbPool> {
-    Router::new().route("/v1/tags", get(get_tags))
+#[derive(Deserialize, Validate)]
+pub struct TagNamePath {
+    #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
+    name: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct TagRequest {
+    #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
+    name: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MergeTagRequest {
+    /// Name of the tag that absorbs the merged tag
+    #[validate(length(min = 1, max = 50), regex(path = *TAG_RE))]
+    into: String,
+}
+
+/// Only editors and admins may manage tags
+fn ensure_editor(current: &CurrentUser) -> Result<(), AppError> {
+    if current.is_editor() {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden(String::from(
+            "Only editors can manage tags",
+        )))
+    }
+}
+
+/// Map the outcome of a tag operation to a response
+fn tag_response(outcome: TagOutcome, name: &str) -> Result<Json<ApiResponse<Tag>>, AppError> {
+    match outcome {
+        TagOutcome::Done(tag) => Ok(Json(ApiResponse::success(tag))),
+        TagOutcome::NotFound(missing) => {
+            Err(AppError::NotFound(format!("Tag not found: {}", missing)))
+        }
+        TagOutcome::NameTaken => Err(AppError::Conflict(format!("Tag already exists: {}", name))),
+        TagOutcome::InUse(count) => Err(AppError::Conflict(format!(
+            "Tag {} is still used by {} posts",
+            name, count
+        ))),
+    }
+}
+
+pub async fn create_tag(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Json(input)): Valid<Json<TagRequest>>,
+) -> Result<Json<ApiResponse<Tag>>, AppError> {
+    ensure_editor(&current)?;
+    let client = pool.get().await?;
+
+    services::tag::create_tag(&client, &input.name)
+        .await?
+        .map(|tag| Json(ApiResponse::success(tag)))
+        .ok_or_else(|| AppError::Conflict(format!("Tag already exists: {}", input.name)))
+}
+
+pub async fn rename_tag(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Path(path)): Valid<Path<TagNamePath>>,
+    Valid(Json(input)): Valid<Json<TagRequest>>,
+) -> Result<Json<ApiResponse<Tag>>, AppError> {
+    ensure_editor(&current)?;
+    let mut client = pool.get().await?;
+
+    let outcome = services::tag::rename_tag(&mut client, &path.name, &input.name).await?;
+    tag_response(outcome, &input.name)
+}
+
+pub async fn merge_tag(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Path(path)): Valid<Path<TagNamePath>>,
+    Valid(Json(input)): Valid<Json<MergeTagRequest>>,
+) -> Result<Json<ApiResponse<Tag>>, AppError> {
+    ensure_editor(&current)?;
+    if path.name == input.into {
+        return Err(AppError::BadRequest(String::from(
+            "Cannot merge a tag into itself",
+        )));
+    }
+    let mut client = pool.get().await?;
+
+    let outcome = services::tag::merge_tags(&mut client, &path.name, &input.into).await?;
+    tag_response(outcome, &input.into)
+}
+
+pub async fn delete_tag(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Path(path)): Valid<Path<TagNamePath>>,
+) -> Result<Json<ApiResponse<Tag>>, AppError> {
+    ensure_editor(&current)?;
+    let mut client = pool.get().await?;
+
+    let outcome = services::tag::delete_tag(&mut client, &path.name).await?;
+    tag_response(outcome, &path.name)
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/v1/tags", get(get_tags).post(create_tag))
+        .route("/v1/tags/{name}", patch(rename_tag).delete(delete_tag))
+        .route("/v1/tags/{name}/merge", post(merge_tag))
 }
@@ -0,0 +1,267 @@
+use super::media::{discard_media, remove_media, store_upload, upload_body_limit};
+use super::post::{PaginationQuery, USERNAME_RE, get_pagination_params, page_response};
+use crate::auth::{CurrentUser, Viewer};
+use crate::config::MediaConfig;
+use crate::database::DbPool;
+use crate::error::AppError;
+use crate::models::user::{User, UserProfile};
+use crate::response::ApiResponse;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json, Router,
+    extract::{Multipart, Path, Query, RawQuery, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, patch, put},
+};
+use axum_valid::Valid;
+use serde::Deserialize;
+use validator::{Validate, ValidateUrl, ValidationError};
+
+/// Widest image file used as a profile image
+const AVATAR_WIDTH: i32 = 320;
+
+#[derive(Deserialize, Validate)]
+pub struct UsernamePath {
+    #[validate(length(min = 1, max = 50), regex(path = *USERNAME_RE))]
+    pub username: String,
+}
+
+fn user_not_found(username: &str) -> AppError {
+    AppError::NotFound(format!("User not found: {}", username))
+}
+
+/// Whether `link` uses a scheme that is safe to render as a link
+fn is_web_link(link: &str) -> bool {
+    link.split_once("://").is_some_and(|(scheme, _)| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    })
+}
+
+/// Every link must be an absolute http(s) URL of at most 2048 characters
+fn validate_links(links: &[String]) -> Result<(), ValidationError> {
+    if links
+        .iter()
+        .all(|l| l.len() <= 2048 && is_web_link(l) && l.validate_url())
+    {
+        Ok(())
+    } else {
+        Err(ValidationError::new("url"))
+    }
+}
+
+#[derive(Deserialize, Validate)]
+pub struct UpdateMeRequest {
+    #[validate(length(min = 3, max = 50), regex(path = *USERNAME_RE))]
+    username: Option<String>,
+    /// An empty bio removes it
+    #[validate(length(max = 500))]
+    bio: Option<String>,
+    /// Replaces all links
+    #[validate(length(max = 5), custom(function = "validate_links"))]
+    links: Option<Vec<String>>,
+}
+
+/// Update the caller's account and profile. Old usernames keep redirecting to
+/// the new one.
+pub async fn update_me(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Json(input)): Valid<Json<UpdateMeRequest>>,
+) -> Result<Json<ApiResponse<UserProfile>>, AppError> {
+    let mut client = pool.get().await?;
+    // One transaction, so a failed profile update does not leave a rename behind
+    let tx = client.transaction().await?;
+    let user = match input.username {
+        Some(username) => services::user::change_username(&tx, current.user.id, &username)
+            .await?
+            .ok_or_else(|| AppError::Conflict(format!("Username already taken: {}", username)))?,
+        None => current.user,
+    };
+    if input.bio.is_some() || input.links.is_some() {
+        services::user::update_profile(
+            &tx,
+            user.id,
+            input.bio.as_deref().map(str::trim),
+            input.links.as_deref(),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    let profile = services::user::get_profile(&client, &user.username, None)
+        .await?
+        .ok_or_else(|| user_not_found(&user.username))?;
+
+    Ok(Json(ApiResponse::success(profile)))
+}
+
+/// Answer a request for a renamed user with `301` to the same path under the
+/// current username, or `404` if nobody was ever called `username`
+async fn redirect_renamed_user(
+    client: &tokio_postgres::Client,
+    username: &str,
+    suffix: &str,
+    query: Option<String>,
+) -> Result<Response, AppError> {
+    let current = services::user::resolve_moved_username(client, username)
+        .await?
+        .ok_or_else(|| user_not_found(username))?;
+    let mut location = format!("/v1/users/{}{}", current, suffix);
+    if let Some(query) = query {
+        location.push('?');
+        location.push_str(&query);
+    }
+
+    Ok((
+        StatusCode::MOVED_PERMANENTLY,
+        [(header::LOCATION, location)],
+    )
+        .into_response())
+}
+
+pub async fn get_profile(
+    State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
+    Valid(Path(path)): Valid<Path<UsernamePath>>,
+) -> Result<Response, AppError> {
+    let client = pool.get().await?;
+    match services::user::get_profile(&client, &path.username, viewer).await? {
+        Some(profile) => Ok(Json(ApiResponse::success(profile)).into_response()),
+        None => redirect_renamed_user(&client, &path.username, "", None).await,
+    }
+}
+
+/// List the posts of a user; the user themselves also sees drafts and other
+/// unpublished posts
+pub async fn get_user_posts(
+    State(pool): State<DbPool>,
+    Viewer(viewer): Viewer,
+    Valid(Path(path)): Valid<Path<UsernamePath>>,
+    Valid(query): Valid<Query<PaginationQuery>>,
+    RawQuery(raw_query): RawQuery,
+) -> Result<Response, AppError> {
+    let client = pool.get().await?;
+    let Some(user_id) = services::user::get_user_id(&client, &path.username).await? else {
+        return redirect_renamed_user(&client, &path.username, "/posts", raw_query).await;
+    };
+    let options = get_pagination_params(&query)?;
+    let (limit, offset) = (options.limit, options.offset);
+
+    let mut page = if viewer == Some(user_id) {
+        services::post::get_own_posts(&client, user_id, None, options).await?
+    } else {
+        services::post::get_posts_by_author(&client, &path.username, options).await?
+    };
+    services::reaction::fill_viewer_flags(&client, viewer, &mut page.posts).await?;
+
+    Ok(page_response(page, limit, offset).into_response())
+}
+
+/// Follow or unfollow a user and return their updated profile
+async fn follow(
+    pool: DbPool,
+    current: CurrentUser,
+    username: &str,
+    active: bool,
+) -> Result<Json<ApiResponse<UserProfile>>, AppError> {
+    let client = pool.get().await?;
+    let user_id = services::user::get_user_id(&client, username)
+        .await?
+        .ok_or_else(|| user_not_found(username))?;
+    if user_id == current.user.id {
+        return Err(AppError::BadRequest(String::from(
+            "You cannot follow yourself",
+        )));
+    }
+
+    services::user::set_follow(&client, current.user.id, user_id, active).await?;
+    let profile = services::user::get_profile(&client, username, Some(current.user.id))
+        .await?
+        .ok_or_else(|| user_not_found(username))?;
+
+    Ok(Json(ApiResponse::success(profile)))
+}
+
+pub async fn follow_user(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Path(path)): Valid<Path<UsernamePath>>,
+) -> Result<Json<ApiResponse<UserProfile>>, AppError> {
+    follow(pool, current, &path.username, true).await
+}
+
+pub async fn unfollow_user(
+    State(pool): State<DbPool>,
+    current: CurrentUser,
+    Valid(Path(path)): Valid<Path<UsernamePath>>,
+) -> Result<Json<ApiResponse<UserProfile>>, AppError> {
+    follow(pool, current, &path.username, false).await
+}
+
+/// Upload a profile image as the `file` field of a multipart form and use
+/// its copy closest to 320 pixels wide as `image`. The previous avatar's
+/// upload is deleted.
+pub async fn upload_avatar(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    multipart: Multipart,
+) -> Result<Json<ApiResponse<User>>, AppError> {
+    let media = store_upload(&state, &current, multipart).await?;
+    let result = async {
+        let mut client = state.pool.get().await?;
+        let (user, previous) = services::user::set_avatar(
+            &mut client,
+            current.user.id,
+            Some((media.id, media.url_for_width(AVATAR_WIDTH))),
+        )
+        .await?;
+        Ok::<_, AppError>((client, user, previous))
+    }
+    .await;
+    let (mut client, user, previous) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            // The new upload would be left without anything referring to it
+            discard_media(&state, media.id).await;
+            return Err(e);
+        }
+    };
+    if let Some(previous) = previous {
+        remove_media(&state, &mut client, previous).await?;
+    }
+
+    Ok(Json(ApiResponse::success(user)))
+}
+
+/// Remove the caller's profile image and delete its upload
+pub async fn delete_avatar(
+    State(state): State<AppState>,
+    current: CurrentUser,
+) -> Result<Json<ApiResponse<User>>, AppError> {
+    let mut client = state.pool.get().await?;
+    let (user, previous) = services::user::set_avatar(&mut client, current.user.id, None).await?;
+    if let Some(previous) = previous {
+        remove_media(&state, &mut client, previous).await?;
+    }
+
+    Ok(Json(ApiResponse::success(user)))
+}
+
+pub fn routes(config: &MediaConfig) -> Router<AppState> {
+    Router::new()
+        .route("/v1/me", patch(update_me))
+        .route(
+            "/v1/me/avatar",
+            put(upload_avatar)
+                .layer(upload_body_limit(config))
+                .delete(delete_avatar),
+        )
+        .route("/v1/users/{username}", get(get_profile))
+        .route("/v1/users/{username}/posts", get(get_user_posts))
+        .route(
+            "/v1/users/{username}/follow",
+            put(follow_user).delete(unfollow_user),
+        )
+}
@@ -1,11 +1,19 @@
+mod auth;
+mod cache;
 mod config;
 mod database;
 mod error;
 mod handlers;
+mod markdown;
+mod media;
+mod migrations;
 mod models;
 mod response;
 mod services;
+mod state;
+mod storage;
 
+use std::net::SocketAddr;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -29,7 +37,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let config = config::Config::from_env();
+    let mut config = config::Config::from_env();
 
     // Create connection pool with configuration from environment
     let pool = database::create_pool(&config.database_url, &config.db_pool).map_err(|e| {
@@ -44,13 +52,109 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.db_pool.connection_timeout
     );
 
-    let app = handlers::create_router().with_state(pool);
+    // `axumbackend migrate` applies migrations and exits
+    let migrate_only = std::env::args().nth(1).as_deref() == Some("migrate");
+    if migrate_only || config.run_migrations {
+        let applied = migrations::run(&pool)
+            .await
+            .map_err(|e| format!("Failed to run migrations: {}", e))?;
+        tracing::info!(
+            "Database schema up to date ({} migrations applied)",
+            applied
+        );
+    }
+    if migrate_only {
+        return Ok(());
+    }
+
+    // Title similarity queries fail outright without pg_trgm
+    if config.related.title_similarity {
+        let client = pool
+            .get()
+            .await
+            .map_err(|e| format!("Failed to connect to database: {}", e))?;
+        let available = services::post::trigram_available(&client)
+            .await
+            .map_err(|e| format!("Failed to check for pg_trgm: {}", e))?;
+        if !available {
+            tracing::warn!(
+                "RELATED_TITLE_SIMILARITY is set but pg_trgm is not installed; related posts ignore titles"
+            );
+            config.related.title_similarity = false;
+        }
+    }
+
+    let state = state::AppState::new(pool, config.clone())
+        .map_err(|e| format!("Failed to load JWT keys: {}", e))?;
+    if state.jwt.is_none() {
+        tracing::warn!("No JWT key configured; authenticated endpoints will reject all requests");
+    }
+
+    state
+        .views
+        .clone()
+        .spawn_flusher(state.pool.clone(), config.views.flush_interval);
+    services::trending::spawn_refresher(state.pool.clone(), config.trending_refresh_interval);
+    services::publish::spawn_publisher(state.pool.clone(), config.publish_interval);
+    services::excerpt::spawn_backfill(state.pool.clone());
+    if !config.trash.retention.is_zero() {
+        services::trash::spawn_purger(
+            state.pool.clone(),
+            config.trash.retention,
+            config.trash.purge_interval,
+        );
+    }
+    let views = state.views.clone();
+    let pool = state.pool.clone();
+
+    let app = handlers::create_router(state);
 
     let addr = format!("0.0.0.0:{}", config.port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .map_err(|e| format!("Failed to bind to {}: {}", addr, e))?;
     tracing::info!("Server listening on {}", addr);
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
+
+    // Write out views buffered since the last periodic flush
+    match pool.get().await {
+        Ok(client) => {
+            if let Err(e) = views.flush(&client).await {
+                tracing::error!("Failed to flush view counts on shutdown: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Failed to flush view counts on shutdown: {}", e),
+    }
     Ok(())
 }
+
+/// Resolve when the process receives Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutting down");
+}
@@ -0,0 +1,442 @@
+use crate::models::post::{BodyFormat, TocEntry};
+use once_cell::sync::Lazy;
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashSet;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+use uuid::Uuid;
+
+/// Prefix given to footnote ids; heading anchors that would still collide,
+/// like the one of "Fn 1", are numbered on by `toc`
+const FOOTNOTE_ID_PREFIX: &str = "fn-";
+
+/// Class naming of highlighted code tokens, e.g. `hl-keyword hl-control`
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Bundled syntect theme the highlighting stylesheet is generated from
+const HIGHLIGHT_THEME: &str = "InspiredGitHub";
+
+/// Average reading speed used for `reading_time_minutes`
+const WORDS_PER_MINUTE: usize = 200;
+
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+static HIGHLIGHT_CSS: Lazy<String> = Lazy::new(|| {
+    let themes = ThemeSet::load_defaults();
+    css_for_theme_with_class_style(&themes.themes[HIGHLIGHT_THEME], HIGHLIGHT_CLASS_STYLE)
+        .expect("bundled theme converts to CSS")
+});
+
+/// CommonMark plus the GitHub extensions posts may use
+fn parser(markdown: &str) -> Parser<'_> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH;
+    Parser::new_ext(markdown, options)
+}
+
+/// Allow-list applied to rendered HTML: ammonia's safe defaults plus the
+/// classes used by footnotes and highlighted code. Generated heading anchors
+/// and footnote ids are rendered behind `marker`, which is removed here; every
+/// other `id` comes from raw HTML and is dropped, so raw HTML can neither claim
+/// element ids nor duplicate a generated one.
+fn sanitizer(marker: String) -> ammonia::Builder<'static> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("sup", &["class"])
+        .add_tag_attributes("div", &["class", "id"])
+        .add_tag_attributes("h1", &["id"])
+        .add_tag_attributes("h2", &["id"])
+        .add_tag_attributes("h3", &["id"])
+        .add_tag_attributes("h4", &["id"])
+        .add_tag_attributes("h5", &["id"])
+        .add_tag_attributes("h6", &["id"])
+        .attribute_filter(move |_, attribute, value| match attribute {
+            "id" => value.strip_prefix(marker.as_str()).map(Into::into),
+            // Footnote references link to their marked definition ids
+            "href" => match value
+                .strip_prefix('#')
+                .and_then(|v| v.strip_prefix(marker.as_str()))
+            {
+                Some(id) => Some(format!("#{}", id).into()),
+                None => Some(value.into()),
+            },
+            _ => Some(value.into()),
+        });
+    builder
+}
+
+/// Turn heading text into an anchor: lowercase words joined by `-`
+fn anchor_slug(text: &str) -> String {
+    let mut anchor = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            anchor.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !anchor.ends_with('-') {
+            anchor.push('-');
+        }
+    }
+    let anchor = anchor.trim_matches('-');
+    if anchor.is_empty() {
+        String::from("section")
+    } else {
+        anchor.to_string()
+    }
+}
+
+/// Headings in document order with unique anchors; repeated titles get `-1`, `-2`, ...
+/// Anchors never reuse the id of a footnote.
+pub fn toc(markdown: &str) -> Vec<TocEntry> {
+    let mut entries = Vec::new();
+    let mut used: HashSet<String> = parser(markdown)
+        .filter_map(|event| match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                Some(format!("{}{}", FOOTNOTE_ID_PREFIX, label))
+            }
+            _ => None,
+        })
+        .collect();
+    let mut current: Option<(u8, String)> = None;
+
+    for event in parser(markdown) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some((level as u8, String::new()))
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if let Some((_, text)) = current.as_mut() {
+                    text.push_str(&t);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((level, text)) = current.take() {
+                    let base = anchor_slug(&text);
+                    let mut anchor = base.clone();
+                    let mut n = 1;
+                    while !used.insert(anchor.clone()) {
+                        anchor = format!("{}-{}", base, n);
+                        n += 1;
+                    }
+                    entries.push(TocEntry {
+                        level,
+                        text: text.trim().to_string(),
+                        anchor,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// Highlight a fenced code block into `<pre><code>` with token classes, or
+/// escape it unchanged when the language is unknown
+fn highlight_code(lang: &str, code: &str) -> String {
+    let syntax = (!lang.is_empty())
+        .then(|| SYNTAX_SET.find_syntax_by_token(lang))
+        .flatten();
+    let highlighted = syntax.and_then(|syntax| {
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAX_SET, HIGHLIGHT_CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+        Some(generator.finalize())
+    });
+    let inner = highlighted.unwrap_or_else(|| ammonia::clean_text(code));
+
+    if lang.is_empty() {
+        format!("<pre><code>{}</code></pre>\n", inner)
+    } else {
+        format!(
+            "<pre><code class=\"language-{}\">{}</code></pre>\n",
+            ammonia::clean_text(lang),
+            inner
+        )
+    }
+}
+
+/// Stylesheet coloring the token classes of highlighted code blocks
+pub fn highlight_css() -> &'static str {
+    &HIGHLIGHT_CSS
+}
+
+/// Render Markdown to HTML that is safe to embed in a page, with highlighted
+/// fenced code and an `id` on every heading matching its `toc` anchor
+pub fn render_html(markdown: &str) -> String {
+    let mut anchors = toc(markdown).into_iter().map(|entry| entry.anchor);
+    // Marks generated ids until sanitizing; random so raw HTML cannot forge it
+    let marker = format!("id-{}-", Uuid::new_v4().simple());
+    let mut events = Vec::new();
+    // Language and source of the fenced code block being read
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in parser(markdown) {
+        if let Some((lang, code)) = code_block.as_mut() {
+            match event {
+                Event::Text(t) => code.push_str(&t),
+                Event::End(TagEnd::CodeBlock) => {
+                    events.push(Event::Html(highlight_code(lang, code).into()));
+                    code_block = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => {
+                let lang = info.split_whitespace().next().unwrap_or("").to_string();
+                code_block = Some((lang, String::new()));
+            }
+            Event::Start(Tag::Heading {
+                level,
+                classes,
+                attrs,
+                ..
+            }) => {
+                let anchor = anchors.next().unwrap_or_default();
+                events.push(Event::Start(Tag::Heading {
+                    level,
+                    id: Some(format!("{}{}", marker, anchor).into()),
+                    classes,
+                    attrs,
+                }));
+            }
+            Event::FootnoteReference(label) => {
+                let id = format!("{}{}{}", marker, FOOTNOTE_ID_PREFIX, label);
+                events.push(Event::FootnoteReference(CowStr::from(id)));
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                let id = format!("{}{}{}", marker, FOOTNOTE_ID_PREFIX, label);
+                events.push(Event::Start(Tag::FootnoteDefinition(id.into())));
+            }
+            event => events.push(event),
+        }
+    }
+
+    let mut html = String::with_capacity(markdown.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    sanitizer(marker).clean(&html).to_string()
+}
+
+/// Render Markdown as plain text, keeping paragraph breaks and dropping markup
+/// and raw HTML
+pub fn render_text(markdown: &str) -> String {
+    let mut text = String::with_capacity(markdown.len());
+    for event in parser(markdown) {
+        match event {
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak | Event::Rule => text.push('\n'),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::CodeBlock
+                | TagEnd::BlockQuote(_)
+                | TagEnd::Table,
+            ) => text.push_str("\n\n"),
+            Event::End(TagEnd::Item) => text.push('\n'),
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => {
+                text.truncate(text.trim_end_matches('\t').len());
+                text.push('\n');
+            }
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            _ => {}
+        }
+    }
+
+    // Collapse the gaps left by nested blocks into single blank lines
+    let mut result = String::with_capacity(text.len());
+    let mut newlines = 0;
+    for c in text.trim().chars() {
+        if c == '\n' {
+            newlines += 1;
+            if newlines <= 2 {
+                result.push(c);
+            }
+        } else {
+            newlines = 0;
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// A single-line plain-text summary of at most `max_chars` characters with
+/// Markdown stripped. Longer text is cut after the last full sentence that
+/// fits, or else at a word boundary followed by `...`.
+pub fn excerpt(markdown: &str, max_chars: usize) -> String {
+    let text = render_text(markdown);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let text = words.join(" ");
+    if text.chars().count() <= max_chars {
+        return text;
+    }
+
+    let cut: String = text.chars().take(max_chars).collect();
+    let sentence_end = cut
+        .match_indices(". ")
+        .chain(cut.match_indices("! "))
+        .chain(cut.match_indices("? "))
+        .map(|(i, _)| i + 1)
+        .max();
+    if let Some(end) = sentence_end.filter(|end| *end >= cut.len() / 2) {
+        return cut[..end].to_string();
+    }
+
+    let cut = match cut.rfind(' ') {
+        Some(i) if i >= cut.len() / 2 => &cut[..i],
+        _ => cut.as_str(),
+    };
+    format!(
+        "{}...",
+        cut.trim_end_matches(|c: char| !c.is_alphanumeric())
+    )
+}
+
+/// A post body prepared for a single-post response
+#[derive(Clone)]
+pub struct RenderedBody {
+    pub body: String,
+    pub toc: Vec<TocEntry>,
+    pub word_count: usize,
+}
+
+impl RenderedBody {
+    /// Minutes needed to read the body, at least one for a non-empty body
+    pub fn reading_time_minutes(&self) -> usize {
+        self.word_count.div_ceil(WORDS_PER_MINUTE)
+    }
+
+    /// Approximate heap size in bytes, for bounding caches
+    pub fn size(&self) -> usize {
+        self.body.len()
+            + self
+                .toc
+                .iter()
+                .map(|e| size_of::<TocEntry>() + e.text.len() + e.anchor.len())
+                .sum::<usize>()
+    }
+}
+
+/// Render a Markdown body in `format` together with its outline and length
+pub fn render(markdown: &str, format: BodyFormat) -> RenderedBody {
+    let text = render_text(markdown);
+    let word_count = text.split_whitespace().count();
+    let body = match format {
+        BodyFormat::Markdown => markdown.to_string(),
+        BodyFormat::Html => render_html(markdown),
+        BodyFormat::Text => text,
+    };
+
+    RenderedBody {
+        body,
+        toc: toc(markdown),
+        word_count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_scripts_and_javascript_links() {
+        let html = render_html(
+            "Hi <script>alert(1)</script>\n\n[click](javascript:alert(1)) \
+             <a href=\"javascript:alert(2)\">raw</a>",
+        );
+        assert!(!html.contains("<script"), "{}", html);
+        assert!(!html.contains("alert(1)"), "{}", html);
+        assert!(!html.contains("javascript:"), "{}", html);
+        assert!(html.contains("click"), "{}", html);
+    }
+
+    #[test]
+    fn drops_ids_not_generated_for_headings_or_footnotes() {
+        let html =
+            render_html("# Title\n\n<div id=\"login-form\">x</div>\n\nNote[^1]\n\n[^1]: Text");
+        assert!(html.contains(r#"<h1 id="title">"#), "{}", html);
+        assert!(html.contains(r#"id="fn-1""#), "{}", html);
+        assert!(!html.contains("login-form"), "{}", html);
+    }
+
+    #[test]
+    fn drops_raw_ids_that_match_generated_ones() {
+        let html = render_html(
+            "<div id=\"title\">raw</div>\n\n# Title\n\n<p id=\"fn-1\">raw</p>\n\n\
+             Note[^1]\n\n[^1]: Text",
+        );
+        assert_eq!(html.matches(r#"id="title""#).count(), 1, "{}", html);
+        assert!(html.contains(r#"<h1 id="title">"#), "{}", html);
+        assert_eq!(html.matches(r#"id="fn-1""#).count(), 1, "{}", html);
+        assert!(html.contains(r##"href="#fn-1""##), "{}", html);
+        assert!(!html.contains("id-"), "{}", html);
+    }
+
+    #[test]
+    fn excerpt_keeps_short_text_whole() {
+        assert_eq!(
+            excerpt("# Hello\n\nSome *bold* text.", 100),
+            "Hello Some bold text."
+        );
+    }
+
+    #[test]
+    fn excerpt_cuts_after_the_last_full_sentence() {
+        let text = "First sentence here. Second one is here. Third sentence runs long.";
+        assert_eq!(
+            excerpt(text, 50),
+            "First sentence here. Second one is here."
+        );
+    }
+
+    #[test]
+    fn excerpt_cuts_at_a_word_boundary_without_a_sentence() {
+        let text = "one two three four five six seven eight nine ten";
+        assert_eq!(excerpt(text, 20), "one two three four...");
+    }
+
+    #[test]
+    fn repeated_headings_get_numbered_anchors() {
+        let markdown = "# Setup\n\n## Setup\n\n### Setup\n\n## Other `code` *here*";
+        let anchors: Vec<String> = toc(markdown).into_iter().map(|e| e.anchor).collect();
+        assert_eq!(anchors, ["setup", "setup-1", "setup-2", "other-code-here"]);
+
+        let html = render_html(markdown);
+        for anchor in &anchors {
+            assert!(html.contains(&format!(r#"id="{}""#, anchor)), "{}", html);
+        }
+    }
+
+    #[test]
+    fn headings_without_words_get_a_placeholder_anchor() {
+        let anchors: Vec<String> = toc("# !!!\n\n# ???")
+            .into_iter()
+            .map(|e| e.anchor)
+            .collect();
+        assert_eq!(anchors, ["section", "section-1"]);
+    }
+
+    #[test]
+    fn heading_anchors_skip_footnote_ids() {
+        let markdown = "# Fn 1
+
+Note[^1]
+
+[^1]: Text";
+        let anchors: Vec<String> = toc(markdown).into_iter().map(|e| e.anchor).collect();
+        assert_eq!(anchors, ["fn-1-1"]);
+
+        let html = render_html(markdown);
+        assert!(html.contains(r#"<h1 id="fn-1-1">"#), "{}", html);
+        assert_eq!(html.matches(r#"id="fn-1""#).count(), 1, "{}", html);
+    }
+}
@@ -0,0 +1,360 @@
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{DecodingError, LimitError, LimitErrorKind};
+use image::imageops::FilterType;
+use image::{
+    AnimationDecoder, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader, Limits,
+};
+use std::io::Cursor;
+
+/// Largest accepted width or height of an uploaded image, in pixels
+const MAX_DIMENSION: u32 = 10_000;
+
+/// Largest accepted image area, summed over all frames of a GIF
+const MAX_PIXELS: u64 = 40_000_000;
+
+/// Most memory a decoder may allocate for one image
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+
+/// Quality of re-encoded JPEG originals
+const JPEG_QUALITY: u8 = 90;
+
+/// Image formats accepted for upload
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageKind {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+}
+
+impl ImageKind {
+    /// Identify an image by its leading magic bytes, ignoring any declared type
+    pub fn sniff(data: &[u8]) -> Option<Self> {
+        match data {
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageKind::Jpeg),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some(ImageKind::Png),
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(ImageKind::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, rest @ ..] if rest.starts_with(b"WEBP") => {
+                Some(ImageKind::Webp)
+            }
+            _ => None,
+        }
+    }
+
+    /// Kind of a stored file, from the extension of its name
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "jpg" => Some(ImageKind::Jpeg),
+            "png" => Some(ImageKind::Png),
+            "gif" => Some(ImageKind::Gif),
+            "webp" => Some(ImageKind::Webp),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageKind::Jpeg => "image/jpeg",
+            ImageKind::Png => "image/png",
+            ImageKind::Gif => "image/gif",
+            ImageKind::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageKind::Jpeg => "jpg",
+            ImageKind::Png => "png",
+            ImageKind::Gif => "gif",
+            ImageKind::Webp => "webp",
+        }
+    }
+
+    fn format(self) -> ImageFormat {
+        match self {
+            ImageKind::Jpeg => ImageFormat::Jpeg,
+            ImageKind::Png => ImageFormat::Png,
+            ImageKind::Gif => ImageFormat::Gif,
+            ImageKind::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+/// One stored file of an uploaded image
+pub struct ImageFile {
+    /// `original`, `webp` for the full-size WebP copy, or `w{width}` when resized
+    pub name: String,
+    pub kind: ImageKind,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl ImageFile {
+    /// Name the file is stored and served under, e.g. `w640.webp`
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", self.name, self.kind.extension())
+    }
+}
+
+/// Drop 16-bit and grayscale pixel formats, which the encoders do not all take
+fn to_8bit(image: DynamicImage) -> DynamicImage {
+    match image {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => image,
+        image if image.color().has_alpha() => DynamicImage::ImageRgba8(image.to_rgba8()),
+        image => DynamicImage::ImageRgb8(image.to_rgb8()),
+    }
+}
+
+/// Encode a still image; the result carries none of the upload's metadata
+fn encode(kind: ImageKind, image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let mut data = Vec::new();
+    match kind {
+        ImageKind::Jpeg => {
+            JpegEncoder::new_with_quality(&mut data, JPEG_QUALITY).encode_image(image)?
+        }
+        kind => image.write_to(&mut Cursor::new(&mut data), kind.format())?,
+    }
+    Ok(data)
+}
+
+fn encode_webp(name: String, image: &DynamicImage) -> Result<ImageFile, ImageError> {
+    Ok(ImageFile {
+        name,
+        kind: ImageKind::Webp,
+        width: image.width(),
+        height: image.height(),
+        data: encode(ImageKind::Webp, image)?,
+    })
+}
+
+fn too_large() -> ImageError {
+    ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError))
+}
+
+fn limits() -> Limits {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    limits
+}
+
+/// Decode every frame of a GIF and encode them again, returning the first
+/// frame and the new GIF
+fn reencode_gif(data: &[u8]) -> Result<(DynamicImage, Vec<u8>), ImageError> {
+    let mut decoder = GifDecoder::new(Cursor::new(data))?;
+    decoder.set_limits(limits())?;
+
+    // Each frame is decoded to a full canvas, so budget the frames together
+    let mut pixels = 0;
+    let mut frames = Vec::new();
+    for frame in decoder.into_frames() {
+        let frame = frame?;
+        pixels += u64::from(frame.buffer().width()) * u64::from(frame.buffer().height());
+        if pixels > MAX_PIXELS {
+            return Err(too_large());
+        }
+        frames.push(frame);
+    }
+    let first = frames
+        .first()
+        .map(|f| DynamicImage::ImageRgba8(f.buffer().clone()))
+        .ok_or_else(|| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormat::Gif.into(),
+                "GIF has no frames",
+            ))
+        })?;
+
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut encoded);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok((first, encoded))
+}
+
+/// Decode an uploaded image and prepare its files: the original re-encoded in
+/// its own format, a full-size WebP copy unless the original is WebP, and a
+/// WebP resized to each of `widths` (widest first) narrower than the original.
+/// Re-encoding drops EXIF and other metadata, so the EXIF orientation is
+/// applied to the pixels first. Animated GIFs keep their animation only in
+/// the original.
+///
+/// # Errors
+/// Returns an error if the data does not decode as `kind`, exceeds the size
+/// limits, or cannot be re-encoded.
+pub fn process_image(
+    data: &[u8],
+    kind: ImageKind,
+    widths: &[u32],
+) -> Result<Vec<ImageFile>, ImageError> {
+    let reader = || {
+        let mut reader = ImageReader::with_format(Cursor::new(data), kind.format());
+        reader.limits(limits());
+        reader
+    };
+    // Check the size from the header before allocating anything for pixels
+    let (width, height) = reader().into_dimensions()?;
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return Err(too_large());
+    }
+
+    let (image, original) = if kind == ImageKind::Gif {
+        reencode_gif(data)?
+    } else {
+        let mut decoder = reader().into_decoder()?;
+        if decoder.total_bytes() > MAX_DECODE_ALLOC {
+            return Err(too_large());
+        }
+        let orientation = decoder.orientation()?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        let image = to_8bit(image);
+        let original = encode(kind, &image)?;
+        (image, original)
+    };
+
+    let mut files = Vec::with_capacity(widths.len() + 2);
+    files.push(ImageFile {
+        name: String::from("original"),
+        kind,
+        width: image.width(),
+        height: image.height(),
+        data: original,
+    });
+    if kind != ImageKind::Webp {
+        files.push(encode_webp(String::from("webp"), &image)?);
+    }
+    // Resize each copy from the previous one, which is cheaper than starting
+    // from the full image every time
+    let mut source = image;
+    for &width in widths {
+        if width < source.width() {
+            source = source.resize(width, u32::MAX, FilterType::Lanczos3);
+            files.push(encode_webp(format!("w{}", width), &source)?);
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Frame, Rgb, RgbImage, Rgba, RgbaImage};
+
+    /// A JPEG `width` by `height` pixels carrying an EXIF orientation tag
+    fn jpeg_with_orientation(width: u32, height: u32, orientation: u8) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, Rgb([200, 0, 0])));
+        let jpeg = encode(ImageKind::Jpeg, &image).unwrap();
+
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+        exif.extend_from_slice(&[0, 0, 0, 0]);
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        data.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(&exif);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    #[test]
+    fn sniffs_each_format_by_magic_bytes() {
+        let cases: [(&[u8], ImageKind); 5] = [
+            (b"\xFF\xD8\xFF\xE0\0\x10JFIF", ImageKind::Jpeg),
+            (b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR", ImageKind::Png),
+            (b"GIF87a\x01\0\x01\0", ImageKind::Gif),
+            (b"GIF89a\x01\0\x01\0", ImageKind::Gif),
+            (b"RIFF\x24\0\0\0WEBPVP8 ", ImageKind::Webp),
+        ];
+        for (data, kind) in cases {
+            assert_eq!(ImageKind::sniff(data), Some(kind));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_and_truncated_data() {
+        let cases: [&[u8]; 7] = [
+            b"",
+            b"\xFF\xD8",
+            b"\x89PNG\r\n",
+            b"GIF88a",
+            b"RIFF\x24\0\0\0WAVEfmt ",
+            b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>",
+            b"%PDF-1.7",
+        ];
+        for data in cases {
+            assert_eq!(ImageKind::sniff(data), None, "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn applies_exif_orientation_and_strips_metadata() {
+        let data = jpeg_with_orientation(40, 20, 6);
+        let files = process_image(&data, ImageKind::Jpeg, &[10]).unwrap();
+
+        let names: Vec<_> = files.iter().map(ImageFile::file_name).collect();
+        assert_eq!(names, ["original.jpg", "webp.webp", "w10.webp"]);
+        for file in &files[..2] {
+            assert_eq!((file.width, file.height), (20, 40));
+        }
+        assert_eq!((files[2].width, files[2].height), (10, 20));
+        assert!(!files[0].data.windows(4).any(|w| w == b"Exif"));
+    }
+
+    #[test]
+    fn keeps_gif_animation_in_the_original() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            let frames = [[255, 0, 0, 255], [0, 0, 255, 255]]
+                .map(|color| Frame::new(RgbaImage::from_pixel(8, 4, Rgba(color))));
+            encoder.encode_frames(frames).unwrap();
+        }
+        let files = process_image(&data, ImageKind::Gif, &[]).unwrap();
+
+        let decoder = GifDecoder::new(Cursor::new(&files[0].data)).unwrap();
+        assert_eq!(decoder.into_frames().count(), 2);
+        assert_eq!(
+            (files[1].kind, files[1].width, files[1].height),
+            (ImageKind::Webp, 8, 4)
+        );
+    }
+
+    /// CRC-32 as used by PNG chunks
+    fn crc32(bytes: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in bytes {
+            crc ^= u32::from(byte);
+            for _ in 0..8 {
+                crc = if crc & 1 == 1 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        !crc
+    }
+
+    #[test]
+    fn rejects_images_over_the_pixel_budget() {
+        // A header claiming 10000 by 5000 pixels and no pixel data
+        let mut header = 10_000u32.to_be_bytes().to_vec();
+        header.extend_from_slice(&5_000u32.to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        for (kind, body) in [(b"IHDR", &header[..]), (b"IDAT", &[]), (b"IEND", &[])] {
+            let chunk = [&kind[..], body].concat();
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(&chunk);
+            data.extend_from_slice(&crc32(&chunk).to_be_bytes());
+        }
+
+        let error = process_image(&data, ImageKind::Png, &[]).err().unwrap();
+        assert!(matches!(error, ImageError::Limits(_)), "{:?}", error);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::database::DbPool;
+use deadpool_postgres::PoolError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use tokio_postgres::Client;
+
+/// Advisory lock key held while migrating so concurrent replicas apply migrations once
+const MIGRATION_LOCK_KEY: i64 = 0x6178_756d_6d69_6772; // "axummigr"
+
+/// A versioned SQL migration compiled into the binary
+struct Migration {
+    version: i64,
+    name: &'static str,
+    sql: &'static str,
+}
+
+/// All migrations in the order they must be applied.
+/// Never edit an applied migration; add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../migrations/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "auth",
+        sql: include_str!("../migrations/0002_auth.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "post_search",
+        sql: include_str!("../migrations/0003_post_search.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "comments",
+        sql: include_str!("../migrations/0004_comments.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "reactions",
+        sql: include_str!("../migrations/0005_reactions.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "user_roles",
+        sql: include_str!("../migrations/0006_user_roles.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "tag_search",
+        sql: include_str!("../migrations/0007_tag_search.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "related_posts",
+        sql: include_str!("../migrations/0008_related_posts.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "trending",
+        sql: include_str!("../migrations/0009_trending.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "post_status",
+        sql: include_str!("../migrations/0010_post_status.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "post_revisions",
+        sql: include_str!("../migrations/0011_post_revisions.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "url_history",
+        sql: include_str!("../migrations/0012_url_history.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "post_excerpt",
+        sql: include_str!("../migrations/0013_post_excerpt.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "media",
+        sql: include_str!("../migrations/0014_media.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "user_profiles",
+        sql: include_str!("../migrations/0015_user_profiles.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "comment_depth",
+        sql: include_str!("../migrations/0016_comment_depth.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "user_avatar_media",
+        sql: include_str!("../migrations/0017_user_avatar_media.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "posts_published_at_index",
+        sql: include_str!("../migrations/0018_posts_published_at_index.sql"),
+    },
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Database(tokio_postgres::Error),
+    Pool(PoolError),
+    ChecksumMismatch { version: i64, name: String },
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Database(e) => match e.as_db_error() {
+                Some(db) => write!(f, "database error: {}", db),
+                None => write!(f, "database error: {}", e),
+            },
+            MigrationError::Pool(e) => write!(f, "connection pool error: {}", e),
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "migration {} ({}) was modified after it was applied",
+                version, name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<tokio_postgres::Error> for MigrationError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        MigrationError::Database(err)
+    }
+}
+
+impl From<PoolError> for MigrationError {
+    fn from(err: PoolError) -> Self {
+        MigrationError::Pool(err)
+    }
+}
+
+fn checksum(sql: &str) -> String {
+    format!("{:x}", Sha256::digest(sql.as_bytes()))
+}
+
+/// Apply all pending migrations, returning how many were applied.
+///
+/// Holds a session-level advisory lock for the duration so that replicas starting
+/// at the same time wait for each other instead of racing. Each migration runs in
+/// its own transaction together with its `schema_migrations` record.
+///
+/// # Errors
+/// Fails if a migration cannot be applied or an applied migration's checksum no
+/// longer matches the embedded SQL.
+pub async fn run(pool: &DbPool) -> Result<usize, MigrationError> {
+    let mut client = pool.get().await?;
+
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await?;
+    let result = apply_pending(&mut client).await;
+    let unlock = client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY])
+        .await;
+
+    let applied = result?;
+    unlock?;
+    Ok(applied)
+}
+
+async fn apply_pending(client: &mut Client) -> Result<usize, MigrationError> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                 version BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 checksum TEXT NOT NULL,
+                 applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+             )",
+        )
+        .await?;
+
+    let applied: HashMap<i64, String> = client
+        .query("SELECT version, checksum FROM schema_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    if let Some(latest) = applied.keys().max()
+        && !MIGRATIONS.iter().any(|m| m.version == *latest)
+    {
+        tracing::warn!(
+            "Database has migration {} which this build does not know about",
+            latest
+        );
+    }
+
+    let mut count = 0;
+    for migration in MIGRATIONS {
+        let sum = checksum(migration.sql);
+        if let Some(existing) = applied.get(&migration.version) {
+            if *existing != sum {
+                return Err(MigrationError::ChecksumMismatch {
+                    version: migration.version,
+                    name: migration.name.to_string(),
+                });
+            }
+            continue;
+        }
+
+        tracing::info!(
+            "Applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration.sql).await?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name, checksum) VALUES ($1, $2, $3)",
+            &[&migration.version, &migration.name, &sum],
+        )
+        .await?;
+        tx.commit().await?;
+        count += 1;
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,12 @@
+use super::user::User;
+use serde::{Deserialize, Serialize};
+
+/// Tokens issued on registration, login and refresh
+#[derive(Serialize, Deserialize)]
+pub struct AuthTokens {
+    pub user: User,
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
@@ -0,0 +1,45 @@
+use super::user::User;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct Comment {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    /// 0 for top-level comments, one more for each level of replies
+    pub depth: i32,
+    /// `None` once the comment is deleted; kept so its replies stay threaded
+    pub body: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub user: User,
+    pub replies: Vec<Comment>,
+}
+
+impl From<&Row> for Comment {
+    fn from(row: &Row) -> Self {
+        let deleted_at: Option<DateTime<Utc>> = row.get(6);
+        let body: String = row.get(3);
+
+        Self {
+            id: row.get(0),
+            post_id: row.get(1),
+            parent_id: row.get(2),
+            depth: row.get(10),
+            body: deleted_at.is_none().then_some(body),
+            created_at: row.get(4),
+            updated_at: row.get(5),
+            deleted_at,
+            user: User {
+                id: row.get(7),
+                username: row.get(8),
+                image: row.get(9),
+            },
+            replies: Vec::new(),
+        }
+    }
+}
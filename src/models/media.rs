@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// An uploaded image with the files generated from it
+#[derive(Serialize)]
+pub struct Media {
+    pub id: Uuid,
+    pub created_by: Uuid,
+    /// Type detected from the uploaded bytes
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    /// Size of the original upload in bytes
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+    /// The original first, then the WebP copies from widest to narrowest
+    pub variants: Vec<MediaVariant>,
+}
+
+/// One stored file of an uploaded image
+#[derive(Serialize)]
+pub struct MediaVariant {
+    /// `original`, `webp` or `w{width}`
+    pub name: String,
+    #[serde(skip)]
+    pub file_name: String,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    pub size: i64,
+    pub url: String,
+}
+
+impl Media {
+    /// URL of the widest file at most `max_width` pixels wide, or of the
+    /// original when every file is wider
+    pub fn url_for_width(&self, max_width: i32) -> &str {
+        self.variants
+            .iter()
+            .filter(|v| v.width <= max_width)
+            .max_by_key(|v| (v.width, v.name != "original"))
+            .or_else(|| self.variants.first())
+            .map(|v| v.url.as_str())
+            .unwrap_or_default()
+    }
+}
@@ -1,3 +1,7 @@
+pub mod auth;
+pub mod comment;
+pub mod media;
 pub mod post;
+pub mod revision;
 pub mod tag;
 pub mod user;
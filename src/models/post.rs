@@ -13,64 +13,189 @@ pub enum OrderDirection {
     Desc,
 }
 
-#[derive(Serialize, Deserialize)]
+/// How a multi-tag filter matches posts
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TagMode {
+    /// Posts carrying every listed tag
+    All,
+    /// Posts carrying at least one listed tag
+    #[default]
+    Any,
+}
+
+/// Publication state of a post
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    /// Only visible to the author
+    Draft,
+    /// Published automatically at `publish_at`
+    Scheduled,
+    /// Listed everywhere
+    Published,
+    /// Reachable by link but left out of listings, feeds and search
+    Unlisted,
+    /// Withdrawn; only visible to the author
+    Archived,
+}
+
+impl PostStatus {
+    /// Value stored in `posts.status`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostStatus::Draft => "draft",
+            PostStatus::Scheduled => "scheduled",
+            PostStatus::Published => "published",
+            PostStatus::Unlisted => "unlisted",
+            PostStatus::Archived => "archived",
+        }
+    }
+
+    /// Parse a `posts.status` value, treating unknown values as drafts
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "scheduled" => PostStatus::Scheduled,
+            "published" => PostStatus::Published,
+            "unlisted" => PostStatus::Unlisted,
+            "archived" => PostStatus::Archived,
+            _ => PostStatus::Draft,
+        }
+    }
+
+    /// Whether readers other than the author can open the post
+    pub fn is_public(self) -> bool {
+        matches!(self, PostStatus::Published | PostStatus::Unlisted)
+    }
+}
+
+/// Maximum characters of a post excerpt
+pub const EXCERPT_LENGTH: usize = 200;
+
+/// Representation of a post body in responses
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyFormat {
+    /// The Markdown source as written
+    #[default]
+    Markdown,
+    /// Rendered and sanitized HTML
+    Html,
+    /// Plain text without markup
+    Text,
+}
+
+/// A heading of a post body, linking to its anchor in the rendered HTML
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub anchor: String,
+}
+
+/// Time window of a trending ranking
+#[derive(Deserialize, Clone, Copy, Default)]
+pub enum TrendingWindow {
+    #[serde(rename = "24h")]
+    Day,
+    #[default]
+    #[serde(rename = "7d")]
+    Week,
+    #[serde(rename = "30d")]
+    Month,
+}
+
+impl TrendingWindow {
+    pub const ALL: [TrendingWindow; 3] = [
+        TrendingWindow::Day,
+        TrendingWindow::Week,
+        TrendingWindow::Month,
+    ];
+
+    /// Key stored in `trending_posts.period`
+    pub fn key(self) -> &'static str {
+        match self {
+            TrendingWindow::Day => "24h",
+            TrendingWindow::Week => "7d",
+            TrendingWindow::Month => "30d",
+        }
+    }
+
+    /// Length of the window in seconds
+    pub fn seconds(self) -> f64 {
+        match self {
+            TrendingWindow::Day => 24.0 * 3600.0,
+            TrendingWindow::Week => 7.0 * 24.0 * 3600.0,
+            TrendingWindow::Month => 30.0 * 24.0 * 3600.0,
+        }
+    }
+
+    /// Age in seconds at which an event counts half as much as a new one
+    pub fn half_life(self) -> f64 {
+        self.seconds() / 4.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Post {
     pub id: Uuid,
     pub title: String,
+    /// Markdown source, left out of listings
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub body: Option<String>,
+    /// Plain-text summary written by the author or generated from the body
+    pub excerpt: Option<String>,
     pub created_by: Uuid,
     pub slug: String,
     pub photo_url: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub deleted_at: Option<DateTime<Utc>>,
-    pub published: bool,
+    pub status: PostStatus,
+    /// When the post was or will be published
+    pub publish_at: Option<DateTime<Utc>>,
     pub view_count: i64,
     pub like_count: i64,
     pub bookmark_count: i64,
+    pub comment_count: i64,
     pub user: User,
     pub tags: Vec<Tag>,
+    /// Highlighted search match, present only in search results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Whether the authenticated viewer liked the post; absent for anonymous requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liked_by_me: Option<bool>,
+    /// Whether the authenticated viewer bookmarked the post; absent for anonymous requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bookmarked_by_me: Option<bool>,
+    /// Headings of the body, present only in single-post responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toc: Option<Vec<TocEntry>>,
+    /// Present only in single-post responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub word_count: Option<usize>,
+    /// Present only in single-post responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reading_time_minutes: Option<usize>,
 }
 
 impl From<&Row> for Post {
     fn from(row: &Row) -> Self {
-        // Substring body to 200 characters max
-        let body: Option<String> = row.get(2);
-        let body = body.map(|b| {
-            if b.chars().count() > 200 {
-                let truncated_body: String = b.chars().take(200).collect();
-                format!("{}...", truncated_body)
-            } else {
-                b
-            }
-        });
-
-        Self {
-            id: row.get(0),
-            title: row.get(1),
-            body,
-            created_by: row.get(3),
-            slug: row.get(4),
-            photo_url: row.get(5),
-            created_at: row.get(6),
-            updated_at: row.get(7),
-            deleted_at: row.get(8),
-            published: row.get(9),
-            view_count: row.get(10),
-            like_count: row.get(11),
-            bookmark_count: row.get(12),
-            user: User {
-                id: row.get(13),
-                username: row.get(14),
-                image: row.get(15),
-            },
-            tags: Vec::new(),
-        }
+        // Listings carry only the stored excerpt
+        let mut post = Post::from_full(row);
+        post.body = None;
+        post
     }
 }
 
 impl Post {
-    /// Create a Post from a Row without truncating the body
+    /// When the post went public, or when it was written if it never has
+    pub fn published_at(&self) -> DateTime<Utc> {
+        self.publish_at.unwrap_or(self.created_at)
+    }
+
+    /// Create a Post from a Row including the full body
     pub fn from_full(row: &Row) -> Self {
         let body: Option<String> = row.get(2);
 
@@ -78,22 +203,31 @@ impl Post {
             id: row.get(0),
             title: row.get(1),
             body,
+            excerpt: row.get(18),
             created_by: row.get(3),
             slug: row.get(4),
             photo_url: row.get(5),
             created_at: row.get(6),
             updated_at: row.get(7),
             deleted_at: row.get(8),
-            published: row.get(9),
-            view_count: row.get(10),
-            like_count: row.get(11),
-            bookmark_count: row.get(12),
+            status: PostStatus::from_db(row.get(9)),
+            publish_at: row.get(10),
+            view_count: row.get(11),
+            like_count: row.get(12),
+            bookmark_count: row.get(13),
+            comment_count: row.get(14),
             user: User {
-                id: row.get(13),
-                username: row.get(14),
-                image: row.get(15),
+                id: row.get(15),
+                username: row.get(16),
+                image: row.get(17),
             },
             tags: Vec::new(),
+            snippet: None,
+            liked_by_me: None,
+            bookmarked_by_me: None,
+            toc: None,
+            word_count: None,
+            reading_time_minutes: None,
         }
     }
 }
@@ -0,0 +1,80 @@
+use super::user::User;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio_postgres::Row;
+use uuid::Uuid;
+
+/// A snapshot of a post's title, body and tags after one change
+#[derive(Serialize)]
+pub struct PostRevision {
+    pub post_id: Uuid,
+    /// 1 for the first version, incremented on every change
+    pub revision: i32,
+    pub title: String,
+    /// Left out of revision listings
+    #[serde(skip)]
+    pub body: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    /// Who made the change
+    pub user: User,
+}
+
+impl From<&Row> for PostRevision {
+    fn from(row: &Row) -> Self {
+        Self {
+            post_id: row.get(0),
+            revision: row.get(1),
+            title: row.get(2),
+            body: row.get(3),
+            tags: row.get(4),
+            created_at: row.get(5),
+            user: User {
+                id: row.get(6),
+                username: row.get(7),
+                image: row.get(8),
+            },
+        }
+    }
+}
+
+/// Old and new value of a single-line field
+#[derive(Serialize)]
+pub struct FieldChange {
+    pub from: String,
+    pub to: String,
+}
+
+/// How a line differs between two revisions
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LineChange {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a body diff with its 1-based position in either revision
+#[derive(Serialize)]
+pub struct DiffLine {
+    pub change: LineChange,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// Differences between two revisions of a post
+#[derive(Serialize)]
+pub struct RevisionDiff {
+    pub from: i32,
+    pub to: i32,
+    /// Present only when the title changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<FieldChange>,
+    pub tags_added: Vec<String>,
+    pub tags_removed: Vec<String>,
+    /// Body diff in unified format, empty when the body is unchanged
+    pub unified: String,
+    /// Every body line, marked as kept, inserted or deleted
+    pub lines: Vec<DiffLine>,
+}
@@ -7,6 +7,22 @@ pub struct Tag {
     pub id: i32,
     pub name: String,
     pub created_at: Option<DateTime<Utc>>,
+    /// Number of published posts with this tag, present in tag listings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_count: Option<i64>,
+}
+
+/// Ordering of tag listings
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagSort {
+    /// Most used first
+    Popular,
+    /// Alphabetical
+    #[default]
+    Name,
+    /// Newest first
+    Recent,
 }
 
 impl From<&Row> for Tag {
@@ -15,6 +31,7 @@ impl From<&Row> for Tag {
             id: row.get(0),
             name: row.get(1),
             created_at: row.get(2),
+            post_count: None,
         }
     }
 }
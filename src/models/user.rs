@@ -1,4 +1,6 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -7,3 +9,69 @@ pub struct User {
     pub username: String,
     pub image: Option<String>,
 }
+
+/// Public profile of a user
+#[derive(Serialize)]
+pub struct UserProfile {
+    pub id: Uuid,
+    pub username: String,
+    pub image: Option<String>,
+    pub bio: Option<String>,
+    pub links: Vec<String>,
+    /// When the account was created
+    pub joined_at: DateTime<Utc>,
+    /// Number of published posts
+    pub post_count: i64,
+    pub follower_count: i64,
+    pub following_count: i64,
+    /// Whether the caller follows this user, present when a token is sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub followed_by_me: Option<bool>,
+}
+
+/// Permission level stored in `users.role`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    /// Parse a `users.role` value, treating unknown values as the least privileged role
+    pub fn from_db(value: &str) -> Self {
+        match value {
+            "admin" => Role::Admin,
+            "editor" => Role::Editor,
+            _ => Role::User,
+        }
+    }
+}
+
+impl From<&Row> for User {
+    fn from(row: &Row) -> Self {
+        Self {
+            id: row.get(0),
+            username: row.get(1),
+            image: row.get(2),
+        }
+    }
+}
+
+impl From<&Row> for UserProfile {
+    fn from(row: &Row) -> Self {
+        Self {
+            id: row.get(0),
+            username: row.get(1),
+            image: row.get(2),
+            bio: row.get(3),
+            links: row.get(4),
+            joined_at: row.get(5),
+            post_count: row.get(6),
+            follower_count: row.get(7),
+            following_count: row.get(8),
+            followed_by_me: row.get(9),
+        }
+    }
+}
@@ -2,19 +2,27 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
 pub struct Meta {
-    pub total_items: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_items: Option<i64>,
     pub offset: i64,
     pub limit: i64,
-    pub total_pages: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_pages: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<String>,
 }
 
 impl Default for Meta {
     fn default() -> Self {
         Meta {
-            total_items: 0,
+            total_items: Some(0),
             offset: 0,
             limit: 10,
-            total_pages: 0,
+            total_pages: Some(0),
+            next_cursor: None,
+            prev_cursor: None,
         }
     }
 }
@@ -36,11 +44,26 @@ impl<T> ApiResponse<T> {
     }
 
     pub fn with_meta(data: T, total: i64, limit: i64, offset: i64) -> Self {
-        let total_pages = if limit > 0 {
-            (total as f64 / limit as f64).ceil() as i64
-        } else {
-            0
-        };
+        Self::with_cursors(data, Some(total), limit, offset, None, None)
+    }
+
+    /// Build a paginated response with keyset cursors.
+    /// `total` is `None` when the client skipped the count query.
+    pub fn with_cursors(
+        data: T,
+        total: Option<i64>,
+        limit: i64,
+        offset: i64,
+        next_cursor: Option<String>,
+        prev_cursor: Option<String>,
+    ) -> Self {
+        let total_pages = total.map(|total| {
+            if limit > 0 {
+                (total as f64 / limit as f64).ceil() as i64
+            } else {
+                0
+            }
+        });
 
         ApiResponse {
             success: true,
@@ -50,6 +73,8 @@ impl<T> ApiResponse<T> {
                 offset,
                 limit,
                 total_pages,
+                next_cursor,
+                prev_cursor,
             },
         }
     }
@@ -0,0 +1,166 @@
+use crate::models::user::User;
+use chrono::{DateTime, Utc};
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+/// Result of presenting a refresh token for rotation
+pub enum RefreshOutcome {
+    /// The token was valid and has been replaced by the new one
+    Rotated(User),
+    /// The token had already been used or revoked; its whole family is now revoked
+    Reused,
+    /// The token is unknown or expired
+    Invalid,
+}
+
+/// Create a user with a hashed password.
+/// Returns `None` if the username is already taken.
+pub async fn create_user(
+    client: &Client,
+    username: &str,
+    password_hash: &str,
+) -> Result<Option<User>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "INSERT INTO users (id, username, password, created_at, updated_at)
+             VALUES ($1, $2, $3, NOW(), NOW())
+             ON CONFLICT (username) DO NOTHING
+             RETURNING id, username, image",
+            &[&Uuid::new_v4(), &username, &password_hash],
+        )
+        .await?;
+
+    Ok(row.as_ref().map(User::from))
+}
+
+/// Look up a user and their stored password hash by username
+pub async fn find_user_credentials(
+    client: &Client,
+    username: &str,
+) -> Result<Option<(User, Option<String>)>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT id, username, image, password FROM users WHERE username = $1",
+            &[&username],
+        )
+        .await?;
+
+    Ok(row.map(|r| (User::from(&r), r.get(3))))
+}
+
+/// Store the hash of a newly issued refresh token, starting a new token family
+pub async fn store_refresh_token(
+    client: &Client,
+    user_id: Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), tokio_postgres::Error> {
+    client
+        .execute(
+            "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at, created_at)
+             VALUES ($1, $2, $3, $4, $5, NOW())",
+            &[
+                &Uuid::new_v4(),
+                &user_id,
+                &Uuid::new_v4(),
+                &token_hash,
+                &expires_at,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Exchange a refresh token for a new one in the same family.
+///
+/// Presenting a token that was already rotated or revoked is treated as theft:
+/// every token in its family is revoked so the attacker and the victim are both
+/// forced to log in again.
+pub async fn rotate_refresh_token(
+    client: &mut Client,
+    token_hash: &str,
+    new_token_hash: &str,
+    new_expires_at: DateTime<Utc>,
+) -> Result<RefreshOutcome, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let Some(row) = tx
+        .query_opt(
+            "SELECT id, user_id, family_id, expires_at, revoked_at
+             FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE",
+            &[&token_hash],
+        )
+        .await?
+    else {
+        return Ok(RefreshOutcome::Invalid);
+    };
+
+    let id: Uuid = row.get(0);
+    let user_id: Uuid = row.get(1);
+    let family_id: Uuid = row.get(2);
+    let expires_at: DateTime<Utc> = row.get(3);
+    let revoked_at: Option<DateTime<Utc>> = row.get(4);
+
+    if revoked_at.is_some() {
+        tx.execute(
+            "UPDATE refresh_tokens SET revoked_at = NOW()
+             WHERE family_id = $1 AND revoked_at IS NULL",
+            &[&family_id],
+        )
+        .await?;
+        tx.commit().await?;
+        tracing::warn!("Refresh token reuse detected for user {}", user_id);
+        return Ok(RefreshOutcome::Reused);
+    }
+    if expires_at <= Utc::now() {
+        return Ok(RefreshOutcome::Invalid);
+    }
+
+    let Some(user_row) = tx
+        .query_opt(
+            "SELECT id, username, image FROM users WHERE id = $1",
+            &[&user_id],
+        )
+        .await?
+    else {
+        return Ok(RefreshOutcome::Invalid);
+    };
+
+    let new_id = Uuid::new_v4();
+    tx.execute(
+        "INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at, created_at)
+         VALUES ($1, $2, $3, $4, $5, NOW())",
+        &[
+            &new_id,
+            &user_id,
+            &family_id,
+            &new_token_hash,
+            &new_expires_at,
+        ],
+    )
+    .await?;
+    tx.execute(
+        "UPDATE refresh_tokens SET revoked_at = NOW(), replaced_by = $2 WHERE id = $1",
+        &[&id, &new_id],
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(RefreshOutcome::Rotated(User::from(&user_row)))
+}
+
+/// Revoke every token in the family of the given refresh token.
+/// Returns `false` if the token is unknown.
+pub async fn revoke_refresh_token(
+    client: &Client,
+    token_hash: &str,
+) -> Result<bool, tokio_postgres::Error> {
+    let revoked = client
+        .execute(
+            "UPDATE refresh_tokens SET revoked_at = COALESCE(revoked_at, NOW())
+             WHERE family_id = (SELECT family_id FROM refresh_tokens WHERE token_hash = $1)",
+            &[&token_hash],
+        )
+        .await?;
+    Ok(revoked > 0)
+}
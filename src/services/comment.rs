@@ -0,0 +1,258 @@
+use crate::models::comment::Comment;
+use crate::services::post::VISIBLE_FILTER;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use tokio_postgres::{Client, Transaction};
+use uuid::Uuid;
+
+/// Columns selected for a comment, in the positional order read by `Comment::from`
+const COMMENT_COLUMNS: &str = "c.id, c.post_id, c.parent_id, c.body, c.created_at, c.updated_at, c.deleted_at, u.id, u.username, u.image, c.depth";
+
+/// Deepest nesting level of a reply; replies to comments at this depth are
+/// attached to the same parent instead, so threads stay bounded
+pub const MAX_COMMENT_DEPTH: i32 = 8;
+
+/// Top-level comments that are shown: live ones, and deleted ones with a live
+/// reply anywhere below them, matching what `build_tree` keeps
+const VISIBLE_ROOT_FILTER: &str = "c.parent_id IS NULL AND (c.deleted_at IS NULL
+     OR EXISTS (
+         WITH RECURSIVE descendants AS (
+             SELECT r.id, r.deleted_at FROM comments r WHERE r.parent_id = c.id
+             UNION ALL
+             SELECT r.id, r.deleted_at FROM comments r
+             INNER JOIN descendants d ON r.parent_id = d.id
+         )
+         SELECT 1 FROM descendants WHERE deleted_at IS NULL
+     ))";
+
+/// Arrange comments (ordered oldest first) into reply trees, deepest level
+/// first so no recursion is needed. Deleted comments are kept only while they
+/// still have visible replies.
+fn build_tree(mut comments: Vec<Comment>) -> Vec<Comment> {
+    // Stable, so siblings stay oldest first
+    comments.sort_by_key(|c| Reverse(c.depth));
+
+    let mut replies: HashMap<Uuid, Vec<Comment>> = HashMap::new();
+    let mut roots = Vec::new();
+    for mut comment in comments {
+        comment.replies = replies.remove(&comment.id).unwrap_or_default();
+        if comment.deleted_at.is_some() && comment.replies.is_empty() {
+            continue;
+        }
+        match comment.parent_id {
+            Some(parent_id) => replies.entry(parent_id).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+    roots
+}
+
+/// Get one page of a post's top-level comments, oldest first, each with its
+/// full reply tree, along with the number of top-level comments
+pub async fn get_comments_for_post(
+    client: &Client,
+    post_id: Uuid,
+    offset: i64,
+    limit: i64,
+) -> Result<(Vec<Comment>, i64), tokio_postgres::Error> {
+    let count_query = format!(
+        "SELECT COUNT(*) FROM comments c WHERE c.post_id = $1 AND {}",
+        VISIBLE_ROOT_FILTER
+    );
+    let total: i64 = client.query_one(&count_query, &[&post_id]).await?.get(0);
+
+    let query = format!(
+        "WITH RECURSIVE page AS (
+             SELECT c.id FROM comments c
+             WHERE c.post_id = $1 AND {}
+             ORDER BY c.created_at, c.id
+             LIMIT $2 OFFSET $3
+         ),
+         thread AS (
+             SELECT id FROM page
+             UNION ALL
+             SELECT r.id FROM comments r INNER JOIN thread t ON r.parent_id = t.id
+         )
+         SELECT {}
+         FROM comments c
+         INNER JOIN thread t ON c.id = t.id
+         INNER JOIN users u ON c.created_by = u.id
+         ORDER BY c.created_at, c.id",
+        VISIBLE_ROOT_FILTER, COMMENT_COLUMNS
+    );
+    let rows = client.query(&query, &[&post_id, &limit, &offset]).await?;
+
+    Ok((build_tree(rows.iter().map(Comment::from).collect()), total))
+}
+
+/// Get a single comment by id, including deleted ones
+pub async fn get_comment(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Comment>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM comments c
+         INNER JOIN users u ON c.created_by = u.id
+         WHERE c.id = $1",
+        COMMENT_COLUMNS
+    );
+    let row = client.query_opt(&query, &[&id]).await?;
+    Ok(row.as_ref().map(Comment::from))
+}
+
+/// Get the author of a comment that has not been deleted
+pub async fn get_comment_owner(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT created_by FROM comments WHERE id = $1 AND deleted_at IS NULL",
+            &[&id],
+        )
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Result of creating a comment
+pub enum CommentOutcome {
+    Done(Comment),
+    /// The post does not exist or is not visible to readers
+    PostNotFound,
+    /// The comment replied to is not a live comment on the post
+    ParentNotFound(Uuid),
+}
+
+/// Where a reply to `parent_id` is attached: the comment itself, or its own
+/// parent once the thread is `MAX_COMMENT_DEPTH` deep. The parent is locked
+/// so it cannot be deleted before the reply is inserted.
+/// Returns `None` if `parent_id` is not a live comment on `post_id`.
+async fn resolve_reply_parent(
+    tx: &Transaction<'_>,
+    post_id: Uuid,
+    parent_id: Uuid,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = tx
+        .query_opt(
+            "SELECT parent_id, depth FROM comments
+             WHERE id = $1 AND post_id = $2 AND deleted_at IS NULL
+             FOR SHARE",
+            &[&parent_id, &post_id],
+        )
+        .await?;
+    Ok(row.map(|row| {
+        let grandparent: Option<Uuid> = row.get(0);
+        let depth: i32 = row.get(1);
+        match grandparent {
+            Some(grandparent) if depth >= MAX_COMMENT_DEPTH => grandparent,
+            _ => parent_id,
+        }
+    }))
+}
+
+/// Create a comment and bump the post's `comment_count` in one transaction.
+/// The post and the comment replied to are locked while the reply is
+/// inserted, so neither can be hidden or deleted in between.
+pub async fn create_comment(
+    client: &mut Client,
+    post_id: Uuid,
+    author_id: Uuid,
+    parent_id: Option<Uuid>,
+    body: &str,
+) -> Result<CommentOutcome, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let post_query = format!(
+        "SELECT 1 FROM posts p WHERE p.id = $1 AND {} FOR SHARE",
+        VISIBLE_FILTER
+    );
+    if tx.query_opt(&post_query, &[&post_id]).await?.is_none() {
+        return Ok(CommentOutcome::PostNotFound);
+    }
+    let parent_id = match parent_id {
+        Some(parent_id) => match resolve_reply_parent(&tx, post_id, parent_id).await? {
+            Some(parent_id) => Some(parent_id),
+            None => return Ok(CommentOutcome::ParentNotFound(parent_id)),
+        },
+        None => None,
+    };
+
+    let query = format!(
+        "WITH c AS (
+             INSERT INTO comments (id, post_id, parent_id, created_by, body, created_at, updated_at,
+                                   depth)
+             VALUES ($1, $2, $3, $4, $5, NOW(), NOW(),
+                     COALESCE((SELECT depth + 1 FROM comments WHERE id = $3), 0))
+             RETURNING *
+         )
+         SELECT {}
+         FROM c
+         INNER JOIN users u ON c.created_by = u.id",
+        COMMENT_COLUMNS
+    );
+    let row = tx
+        .query_one(
+            &query,
+            &[&Uuid::new_v4(), &post_id, &parent_id, &author_id, &body],
+        )
+        .await?;
+    tx.execute(
+        "UPDATE posts SET comment_count = comment_count + 1 WHERE id = $1",
+        &[&post_id],
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(CommentOutcome::Done(Comment::from(&row)))
+}
+
+/// Replace the body of a live comment on a post readers can see
+pub async fn update_comment(
+    client: &Client,
+    id: Uuid,
+    body: &str,
+) -> Result<Option<Comment>, tokio_postgres::Error> {
+    let query = format!(
+        "UPDATE comments c SET body = $2, updated_at = NOW()
+         FROM posts p
+         WHERE c.id = $1 AND c.deleted_at IS NULL AND p.id = c.post_id AND {}",
+        VISIBLE_FILTER
+    );
+    let updated = client.execute(&query, &[&id, &body]).await?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    get_comment(client, id).await
+}
+
+/// Soft-delete a comment and decrement the post's `comment_count` in one transaction.
+/// Returns `None` if the comment does not exist or is already deleted.
+pub async fn delete_comment(
+    client: &mut Client,
+    id: Uuid,
+) -> Result<Option<Comment>, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let Some(row) = tx
+        .query_opt(
+            "UPDATE comments SET deleted_at = NOW(), updated_at = NOW()
+             WHERE id = $1 AND deleted_at IS NULL
+             RETURNING post_id",
+            &[&id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+    let post_id: Uuid = row.get(0);
+    tx.execute(
+        "UPDATE posts SET comment_count = GREATEST(comment_count - 1, 0) WHERE id = $1",
+        &[&post_id],
+    )
+    .await?;
+    tx.commit().await?;
+
+    get_comment(client, id).await
+}
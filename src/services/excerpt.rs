@@ -0,0 +1,64 @@
+use crate::database::DbPool;
+use crate::markdown;
+use crate::models::post::EXCERPT_LENGTH;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+/// Posts given an excerpt per backfill query
+const BACKFILL_BATCH_SIZE: i64 = 500;
+
+/// Generate the missing excerpts of up to one batch of posts, returning how
+/// many were filled in. `updated_at` is left untouched.
+pub async fn backfill_excerpts(client: &Client) -> Result<u64, tokio_postgres::Error> {
+    let rows = client
+        .query(
+            "SELECT id, body FROM posts WHERE excerpt IS NULL AND body IS NOT NULL LIMIT $1",
+            &[&BACKFILL_BATCH_SIZE],
+        )
+        .await?;
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.get(0)).collect();
+    let excerpts: Vec<String> = rows
+        .iter()
+        .map(|row| markdown::excerpt(row.get(1), EXCERPT_LENGTH))
+        .collect();
+    client
+        .execute(
+            "UPDATE posts p SET excerpt = e.excerpt
+             FROM unnest($1::uuid[], $2::text[]) AS e(id, excerpt)
+             WHERE p.id = e.id AND p.excerpt IS NULL",
+            &[&ids, &excerpts],
+        )
+        .await
+}
+
+/// Fill in the excerpts of posts written before they were stored, batch by
+/// batch until none are left
+pub fn spawn_backfill(pool: DbPool) {
+    tokio::spawn(async move {
+        let client = match pool.get().await {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Failed to get a connection to backfill excerpts: {}", e);
+                return;
+            }
+        };
+        let mut total = 0;
+        loop {
+            match backfill_excerpts(&client).await {
+                Ok(0) => break,
+                Ok(n) => total += n,
+                Err(e) => {
+                    tracing::error!("Failed to backfill post excerpts: {}", e);
+                    return;
+                }
+            }
+        }
+        if total > 0 {
+            tracing::info!("Generated excerpts for {} posts", total);
+        }
+    });
+}
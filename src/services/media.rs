@@ -0,0 +1,184 @@
+use crate::config::SiteConfig;
+use crate::media::ImageFile;
+use crate::models::media::{Media, MediaVariant};
+use std::cmp::Reverse;
+use tokio_postgres::{Client, GenericClient, Row};
+use uuid::Uuid;
+
+/// Columns of a stored image, in the order read by `media_from_row`
+const MEDIA_COLUMNS: &str = "id, created_by, content_type, width, height, size, created_at";
+
+/// Columns of a stored file, in the order read by `variant_from_row`
+const VARIANT_COLUMNS: &str = "name, file_name, content_type, width, height, size";
+
+fn media_from_row(row: &Row, variants: Vec<MediaVariant>) -> Media {
+    Media {
+        id: row.get(0),
+        created_by: row.get(1),
+        content_type: row.get(2),
+        width: row.get(3),
+        height: row.get(4),
+        size: row.get(5),
+        created_at: row.get(6),
+        variants,
+    }
+}
+
+fn variant_from_row(site: &SiteConfig, media_id: Uuid, row: &Row) -> MediaVariant {
+    let file_name: String = row.get(1);
+    MediaVariant {
+        name: row.get(0),
+        url: site.media_url(media_id, &file_name),
+        file_name,
+        content_type: row.get(2),
+        width: row.get(3),
+        height: row.get(4),
+        size: row.get(5),
+    }
+}
+
+/// Record an uploaded image whose `original` and resized `variants` have been
+/// stored
+pub async fn create_media(
+    client: &mut Client,
+    site: &SiteConfig,
+    user_id: Uuid,
+    id: Uuid,
+    original: &ImageFile,
+    variants: &[ImageFile],
+) -> Result<Media, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let row = tx
+        .query_one(
+            &format!(
+                "INSERT INTO media (id, created_by, content_type, width, height, size, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, NOW())
+                 RETURNING {}",
+                MEDIA_COLUMNS
+            ),
+            &[
+                &id,
+                &user_id,
+                &original.kind.content_type(),
+                &(original.width as i32),
+                &(original.height as i32),
+                &(original.data.len() as i64),
+            ],
+        )
+        .await?;
+    let query = format!(
+        "INSERT INTO media_variants (media_id, name, file_name, content_type, width, height, size)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING {}",
+        VARIANT_COLUMNS
+    );
+    let mut stored = Vec::with_capacity(variants.len() + 1);
+    for file in std::iter::once(original).chain(variants) {
+        let variant = tx
+            .query_one(
+                &query,
+                &[
+                    &id,
+                    &file.name,
+                    &file.file_name(),
+                    &file.kind.content_type(),
+                    &(file.width as i32),
+                    &(file.height as i32),
+                    &(file.data.len() as i64),
+                ],
+            )
+            .await?;
+        stored.push(variant_from_row(site, id, &variant));
+    }
+    // Same order as `get_media`: the original, then widest first
+    stored.sort_by_key(|v| (v.name != "original", Reverse(v.width)));
+
+    tx.commit().await?;
+
+    Ok(media_from_row(&row, stored))
+}
+
+pub async fn get_media(
+    client: &Client,
+    site: &SiteConfig,
+    id: Uuid,
+) -> Result<Option<Media>, tokio_postgres::Error> {
+    load_media(client, site, id, "").await
+}
+
+/// Load an image with its files, appending `row_lock` (e.g. `FOR UPDATE`)
+/// to the query of the image row
+async fn load_media(
+    client: &impl GenericClient,
+    site: &SiteConfig,
+    id: Uuid,
+    row_lock: &str,
+) -> Result<Option<Media>, tokio_postgres::Error> {
+    let Some(row) = client
+        .query_opt(
+            &format!(
+                "SELECT {} FROM media WHERE id = $1 {}",
+                MEDIA_COLUMNS, row_lock
+            ),
+            &[&id],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let variants = client
+        .query(
+            &format!(
+                "SELECT {}
+                 FROM media_variants WHERE media_id = $1
+                 ORDER BY name = 'original' DESC, width DESC",
+                VARIANT_COLUMNS
+            ),
+            &[&id],
+        )
+        .await?
+        .iter()
+        .map(|v| variant_from_row(site, id, v))
+        .collect();
+
+    Ok(Some(media_from_row(&row, variants)))
+}
+
+/// Get the uploader of an image, or `None` if it does not exist
+pub async fn get_media_owner(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = client
+        .query_opt("SELECT created_by FROM media WHERE id = $1", &[&id])
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Delete the record of an image, returning it so its stored files can be
+/// removed, and clear the profile image of a user using it as their avatar.
+/// Returns `None` if it does not exist.
+pub async fn delete_media(
+    client: &mut Client,
+    site: &SiteConfig,
+    id: Uuid,
+) -> Result<Option<Media>, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    // Locked so a concurrent delete or avatar change waits for this one
+    let Some(media) = load_media(&tx, site, id, "FOR UPDATE").await? else {
+        return Ok(None);
+    };
+    tx.execute(
+        "UPDATE users SET image = NULL, avatar_media_id = NULL, updated_at = NOW()
+         WHERE avatar_media_id = $1",
+        &[&id],
+    )
+    .await?;
+    tx.execute("DELETE FROM media WHERE id = $1", &[&id])
+        .await?;
+    tx.commit().await?;
+
+    Ok(Some(media))
+}
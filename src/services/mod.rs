@@ -1,2 +1,21 @@
+pub mod auth;
+pub mod comment;
+pub mod excerpt;
+pub mod media;
 pub mod post;
+pub mod publish;
+pub mod reaction;
+pub mod revision;
 pub mod tag;
+pub mod trash;
+pub mod trending;
+pub mod user;
+pub mod view;
+
+use tokio_postgres::error::SqlState;
+
+/// Whether `err` is a unique violation of the named constraint or unique index
+pub fn is_unique_violation(err: &tokio_postgres::Error, constraint: &str) -> bool {
+    err.code() == Some(&SqlState::UNIQUE_VIOLATION)
+        && err.as_db_error().and_then(|e| e.constraint()) == Some(constraint)
+}
@@ -1,10 +1,90 @@
-use crate::models::post::{OrderDirection, Post};
+use crate::markdown;
+use crate::models::post::{
+    EXCERPT_LENGTH, OrderDirection, Post, PostStatus, TagMode, TrendingWindow,
+};
 use crate::models::tag::Tag;
-use std::collections::HashMap;
-use tokio_postgres::Client;
+use crate::services::is_unique_violation;
+use crate::services::revision::record_revision;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, GenericClient, Transaction};
+use uuid::Uuid;
+
+/// Columns selected for a post, in the positional order read by `Post::from` and
+/// `Post::from_full`, with `$body` in the body position
+macro_rules! post_columns {
+    ($body:literal) => {
+        concat!(
+            "p.id, p.title, ",
+            $body,
+            ", p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.status, p.publish_at, p.view_count, p.like_count, p.bookmark_count, p.comment_count, u.id, u.username, u.image, p.excerpt"
+        )
+    };
+}
+
+/// Columns of a single post including its full body
+const POST_COLUMNS: &str = post_columns!("p.body");
+
+/// Columns of a post in a listing, which never transfers the body
+const LIST_COLUMNS: &str = post_columns!("NULL::text");
+
+/// Number of columns in `POST_COLUMNS`; extra select expressions start at this index
+const POST_COLUMN_COUNT: usize = 19;
+
+/// Condition selecting posts visible to readers
+pub const PUBLISHED_FILTER: &str = "p.status = 'published' AND p.deleted_at IS NULL";
+
+/// Posts any reader may open by id or link, including unlisted ones
+pub const VISIBLE_FILTER: &str = "p.status IN ('published', 'unlisted') AND p.deleted_at IS NULL";
+
+/// Text search configuration used for `search_vector` and queries
+const SEARCH_CONFIG: &str = "english";
+
+/// Control characters `ts_headline` puts around matches. Snippets are cut from
+/// raw Markdown, so they are HTML-escaped in `mark_snippet` before these are
+/// turned into `<mark>` tags.
+const SNIPPET_START: char = '\u{2}';
+const SNIPPET_STOP: char = '\u{3}';
+
+/// `ts_headline` options for search result snippets
+const HEADLINE_OPTIONS: &str =
+    "StartSel=\u{2}, StopSel=\u{3}, MaxWords=35, MinWords=15, MaxFragments=2";
+
+/// Escape a `ts_headline` snippet for HTML and wrap its matches in `<mark>`.
+/// Unbalanced markers, e.g. ones typed into the body, never leave a tag open.
+fn mark_snippet(headline: &str) -> String {
+    let mut out = String::with_capacity(headline.len() + 16);
+    let mut open = false;
+    for c in headline.chars() {
+        match c {
+            SNIPPET_START if !open => {
+                out.push_str("<mark>");
+                open = true;
+            }
+            SNIPPET_STOP if open => {
+                out.push_str("</mark>");
+                open = false;
+            }
+            SNIPPET_START | SNIPPET_STOP => {}
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    if open {
+        out.push_str("</mark>");
+    }
+    out
+}
 
 /// Escape special LIKE/ILIKE pattern characters (% and _) to prevent pattern injection
-fn escape_like_pattern(s: &str) -> String {
+pub fn escape_like_pattern(s: &str) -> String {
     s.replace('\\', "\\\\")
         .replace('%', "\\%")
         .replace('_', "\\_")
@@ -12,7 +92,7 @@ fn escape_like_pattern(s: &str) -> String {
 
 /// Fetch and assign tags to posts in a single batch query (avoids N+1)
 async fn fetch_tags_for_posts(
-    client: &Client,
+    client: &impl GenericClient,
     posts: &mut [Post],
 ) -> Result<(), tokio_postgres::Error> {
     if posts.is_empty() {
@@ -48,9 +128,11 @@ async fn fetch_tags_for_posts(
 }
 
 /// Validate order_by field against whitelist using match statement
-/// Returns the validated field name or default "created_at"
-fn validate_order_field(order_by: Option<&str>) -> &'static str {
+/// Returns the validated field name or default "published_at".
+/// `relevance` is only accepted when a search query is present.
+fn validate_order_field(order_by: Option<&str>, searching: bool) -> &'static str {
     match order_by {
+        Some("relevance") if searching => "relevance",
         Some("id") => "id",
         Some("title") => "title",
         Some("created_at") => "created_at",
@@ -58,7 +140,16 @@ fn validate_order_field(order_by: Option<&str>) -> &'static str {
         Some("view_count") => "view_count",
         Some("like_count") => "like_count",
         Some("bookmark_count") => "bookmark_count",
-        _ => "created_at",
+        _ => "published_at",
+    }
+}
+
+/// SQL expression sorted on for a validated order field
+fn order_expression(order_field: &str) -> String {
+    match order_field {
+        // Scheduled posts are dated by when they went out, not when written
+        "published_at" => String::from("COALESCE(p.publish_at, p.created_at)"),
+        field => format!("p.{}", field),
     }
 }
 
@@ -70,85 +161,558 @@ fn get_order_dir(dir: Option<&OrderDirection>) -> &'static str {
     }
 }
 
-pub async fn get_all_posts(
-    client: &Client,
-    offset: i64,
-    limit: i64,
-    search: Option<&str>,
+/// Positional SQL parameters collected while building a dynamic query
+#[derive(Default)]
+struct SqlParams {
+    values: Vec<Box<dyn ToSql + Sync + Send>>,
+}
+
+impl SqlParams {
+    /// Add a parameter and return its placeholder (`$n`)
+    fn push(&mut self, value: impl ToSql + Sync + Send + 'static) -> String {
+        self.values.push(Box::new(value));
+        format!("${}", self.values.len())
+    }
+
+    fn as_refs(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.values
+            .iter()
+            .map(|v| v.as_ref() as &(dyn ToSql + Sync))
+            .collect()
+    }
+}
+
+/// Value of the order field at a cursor boundary, typed per `validate_order_field`
+#[derive(Clone, Debug, PartialEq)]
+enum CursorValue {
+    Id,
+    Text(String),
+    Time(DateTime<Utc>),
+    Count(i64),
+    Rank(f32),
+}
+
+/// Decoded keyset cursor: the `(order_field, id)` tuple of a boundary row
+pub struct Cursor {
+    value: CursorValue,
+    id: Uuid,
+    backward: bool,
+}
+
+/// Wire format of a cursor before it is base64url encoded
+#[derive(Serialize, Deserialize)]
+struct EncodedCursor {
+    f: String,
+    d: String,
+    v: Option<String>,
+    id: Uuid,
+    #[serde(default)]
+    b: bool,
+}
+
+impl Cursor {
+    /// Capture the boundary values of `post` for the given ordering.
+    /// `rank` is the post's search rank when ordering by relevance.
+    fn from_post(post: &Post, order_field: &str, rank: Option<f32>, backward: bool) -> Self {
+        let value = match order_field {
+            "id" => CursorValue::Id,
+            "relevance" => CursorValue::Rank(rank.unwrap_or_default()),
+            "title" => CursorValue::Text(post.title.clone()),
+            "updated_at" => CursorValue::Time(post.updated_at),
+            "view_count" => CursorValue::Count(post.view_count),
+            "like_count" => CursorValue::Count(post.like_count),
+            "bookmark_count" => CursorValue::Count(post.bookmark_count),
+            "created_at" => CursorValue::Time(post.created_at),
+            _ => CursorValue::Time(post.published_at()),
+        };
+        Self {
+            value,
+            id: post.id,
+            backward,
+        }
+    }
+
+    fn encode(&self, order_field: &str, order_dir: &str) -> String {
+        let v = match &self.value {
+            CursorValue::Id => None,
+            CursorValue::Text(s) => Some(s.clone()),
+            CursorValue::Time(t) => Some(t.to_rfc3339()),
+            CursorValue::Count(n) => Some(n.to_string()),
+            CursorValue::Rank(r) => Some(r.to_string()),
+        };
+        let encoded = EncodedCursor {
+            f: order_field.to_string(),
+            d: order_dir.to_string(),
+            v,
+            id: self.id,
+            b: self.backward,
+        };
+        let json = serde_json::to_vec(&encoded).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+}
+
+/// Decode an opaque cursor, checking it was issued for the requested ordering.
+/// Returns `None` if the cursor is malformed or belongs to a different ordering.
+pub fn decode_cursor(
+    encoded: &str,
     order_by: Option<&str>,
     order_direction: Option<&OrderDirection>,
-) -> Result<(Vec<Post>, i64), tokio_postgres::Error> {
-    let order_field = validate_order_field(order_by);
-    let order_dir = get_order_dir(order_direction);
-    let search_param = search.map(|s| format!("%{}%", escape_like_pattern(s)));
-
-    // Get total count
-    let total: i64 = if let Some(ref search_val) = search_param {
-        client
-            .query_one(
-                "SELECT COUNT(*) FROM posts p INNER JOIN users u ON p.created_by = u.id
-                 WHERE p.published = true AND p.deleted_at IS NULL AND (p.title ILIKE $1 ESCAPE '\\' OR p.body ILIKE $1 ESCAPE '\\' OR u.username ILIKE $1 ESCAPE '\\')",
-                &[search_val],
-            )
-            .await?
-            .get(0)
-    } else {
-        client
-            .query_one(
-                "SELECT COUNT(*) FROM posts WHERE published = true AND deleted_at IS NULL",
-                &[],
-            )
-            .await?
-            .get(0)
+    searching: bool,
+) -> Option<Cursor> {
+    let order_field = validate_order_field(order_by, searching);
+    let bytes = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+    let cursor: EncodedCursor = serde_json::from_slice(&bytes).ok()?;
+    if cursor.f != order_field || cursor.d != get_order_dir(order_direction) {
+        return None;
+    }
+
+    let value = match (order_field, cursor.v) {
+        ("id", _) => CursorValue::Id,
+        ("title", Some(v)) => CursorValue::Text(v),
+        ("relevance", Some(v)) => CursorValue::Rank(v.parse().ok()?),
+        ("created_at" | "updated_at" | "published_at", Some(v)) => {
+            CursorValue::Time(DateTime::parse_from_rfc3339(&v).ok()?.with_timezone(&Utc))
+        }
+        (_, Some(v)) => CursorValue::Count(v.parse().ok()?),
+        (_, None) => return None,
     };
 
-    // Build and execute main query
-    let query = if search_param.is_some() {
+    Some(Cursor {
+        value,
+        id: cursor.id,
+        backward: cursor.b,
+    })
+}
+
+/// Paging and ordering options shared by post listings
+pub struct ListOptions<'a> {
+    pub offset: i64,
+    pub limit: i64,
+    pub search: Option<&'a str>,
+    pub order_by: Option<&'a str>,
+    pub order_direction: Option<&'a OrderDirection>,
+    pub cursor: Option<Cursor>,
+    pub with_count: bool,
+    /// Return whole bodies instead of only the stored `excerpt`
+    pub full_body: bool,
+    /// Only posts with these tags, matched according to `tag_mode`
+    pub tags: Vec<String>,
+    pub tag_mode: TagMode,
+    /// Skip posts carrying any of these tags
+    pub exclude_tags: Vec<String>,
+}
+
+/// A page of posts with optional total and keyset cursors for neighbouring pages
+pub struct PostPage {
+    pub posts: Vec<Post>,
+    pub total: Option<i64>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+/// List posts matching `conditions`, using keyset pagination when a cursor
+/// is given and `LIMIT/OFFSET` otherwise.
+///
+/// When `options.search` is set, posts are matched against `search_vector` with
+/// `websearch_to_tsquery` and each result carries a highlighted `snippet`.
+///
+/// `params` must already hold the parameters referenced by `conditions`.
+async fn list_posts(
+    client: &Client,
+    mut conditions: Vec<String>,
+    mut params: SqlParams,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let order_field = validate_order_field(options.order_by, options.search.is_some());
+    let order_dir = get_order_dir(options.order_direction);
+    let from = "FROM posts p INNER JOIN users u ON p.created_by = u.id";
+
+    // Tag names are unique and de-duplicated by the caller, so matching every
+    // tag means matching as many rows as there are names
+    let tagged = |names: String| {
         format!(
-            "SELECT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.published, p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p INNER JOIN users u ON p.created_by = u.id
-             WHERE p.published = true AND p.deleted_at IS NULL AND (p.title ILIKE $1 ESCAPE '\\' OR p.body ILIKE $1 ESCAPE '\\' OR u.username ILIKE $1 ESCAPE '\\')
-             ORDER BY p.{} {} LIMIT $2 OFFSET $3",
-            order_field, order_dir
+            "(SELECT COUNT(*) FROM posts_to_tags ptt INNER JOIN tags t ON ptt.tag_id = t.id
+              WHERE ptt.post_id = p.id AND t.name = ANY({}))",
+            names
         )
+    };
+    if !options.tags.is_empty() {
+        let wanted = options.tags.len() as i64;
+        let names = params.push(options.tags);
+        conditions.push(match options.tag_mode {
+            TagMode::All => format!("{} = {}", tagged(names), params.push(wanted)),
+            TagMode::Any => format!("{} > 0", tagged(names)),
+        });
+    }
+    if !options.exclude_tags.is_empty() {
+        let names = params.push(options.exclude_tags);
+        conditions.push(format!("{} = 0", tagged(names)));
+    }
+
+    let mut order_expr = order_expression(order_field);
+    let mut search_columns = String::new();
+    if let Some(search) = options.search {
+        let tsquery = format!(
+            "websearch_to_tsquery('{}', {})",
+            SEARCH_CONFIG,
+            params.push(search.to_string())
+        );
+        let rank = format!("ts_rank_cd(p.search_vector, {})", tsquery);
+        conditions.push(format!("p.search_vector @@ {}", tsquery));
+        search_columns = format!(
+            ", {}, ts_headline('{}', COALESCE(p.body, ''), {}, '{}')",
+            rank, SEARCH_CONFIG, tsquery, HEADLINE_OPTIONS
+        );
+        if order_field == "relevance" {
+            order_expr = rank;
+        }
+    }
+
+    let total = if options.with_count {
+        let query = format!(
+            "SELECT COUNT(*) {} WHERE {}",
+            from,
+            conditions.join(" AND ")
+        );
+        Some(client.query_one(&query, &params.as_refs()).await?.get(0))
     } else {
-        format!(
-            "SELECT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.published, p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p INNER JOIN users u ON p.created_by = u.id
-             WHERE p.published = true AND p.deleted_at IS NULL ORDER BY p.{} {} LIMIT $1 OFFSET $2",
-            order_field, order_dir
-        )
+        None
     };
 
-    let rows = if let Some(ref search_val) = search_param {
-        client.query(&query, &[search_val, &limit, &offset]).await?
+    let backward = options.cursor.as_ref().is_some_and(|c| c.backward);
+    // Walking backwards flips the scan direction; rows are reversed afterwards
+    let scan_desc = (order_dir == "DESC") != backward;
+    let (cmp, scan_dir) = if scan_desc {
+        ("<", "DESC")
     } else {
-        client.query(&query, &[&limit, &offset]).await?
+        (">", "ASC")
     };
 
-    let mut posts: Vec<Post> = rows.iter().map(Post::from).collect();
+    if let Some(cursor) = &options.cursor {
+        let id = params.push(cursor.id);
+        let value = match cursor.value.clone() {
+            CursorValue::Id => None,
+            CursorValue::Text(v) => Some(params.push(v)),
+            CursorValue::Time(v) => Some(params.push(v)),
+            CursorValue::Count(v) => Some(params.push(v)),
+            CursorValue::Rank(v) => Some(params.push(v)),
+        };
+        conditions.push(match value {
+            Some(value) => format!("({}, p.id) {} ({}, {})", order_expr, cmp, value, id),
+            None => format!("p.id {} {}", cmp, id),
+        });
+    }
+
+    // Fetch one extra row to learn whether another page exists
+    let limit = params.push(options.limit + 1);
+    let offset = if options.cursor.is_some() {
+        String::new()
+    } else {
+        format!(" OFFSET {}", params.push(options.offset))
+    };
+    let query = format!(
+        "SELECT {}{} {} WHERE {} ORDER BY {} {}, p.id {} LIMIT {}{}",
+        if options.full_body {
+            POST_COLUMNS
+        } else {
+            LIST_COLUMNS
+        },
+        search_columns,
+        from,
+        conditions.join(" AND "),
+        order_expr,
+        scan_dir,
+        scan_dir,
+        limit,
+        offset
+    );
+
+    let rows = client.query(&query, &params.as_refs()).await?;
+    let has_more = rows.len() as i64 > options.limit;
+    let mut ranks: Vec<Option<f32>> = Vec::with_capacity(rows.len());
+    let mut posts: Vec<Post> = rows
+        .iter()
+        .take(options.limit as usize)
+        .map(|row| {
+            let mut post = if options.full_body {
+                Post::from_full(row)
+            } else {
+                Post::from(row)
+            };
+            if options.search.is_some() {
+                ranks.push(Some(row.get(POST_COLUMN_COUNT)));
+                let headline: String = row.get(POST_COLUMN_COUNT + 1);
+                post.snippet = Some(mark_snippet(&headline)).filter(|s| !s.is_empty());
+            } else {
+                ranks.push(None);
+            }
+            post
+        })
+        .collect();
+    if backward {
+        posts.reverse();
+        ranks.reverse();
+    }
     fetch_tags_for_posts(client, &mut posts).await?;
 
-    Ok((posts, total))
+    let (has_next, has_prev) = match &options.cursor {
+        Some(c) if c.backward => (true, has_more),
+        Some(_) => (has_more, true),
+        None => (has_more, options.offset > 0),
+    };
+    let next_cursor = posts.last().filter(|_| has_next).map(|p| {
+        Cursor::from_post(p, order_field, ranks.last().copied().flatten(), false)
+            .encode(order_field, order_dir)
+    });
+    let prev_cursor = posts.first().filter(|_| has_prev).map(|p| {
+        Cursor::from_post(p, order_field, ranks.first().copied().flatten(), true)
+            .encode(order_field, order_dir)
+    });
+
+    Ok(PostPage {
+        posts,
+        total,
+        next_cursor,
+        prev_cursor,
+    })
+}
+
+pub async fn get_all_posts(
+    client: &Client,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let conditions = vec![PUBLISHED_FILTER.to_string()];
+
+    list_posts(client, conditions, SqlParams::default(), options).await
+}
+
+/// List posts bookmarked by `user_id`, including unlisted ones since they can
+/// be bookmarked through their link
+pub async fn get_bookmarked_posts(
+    client: &Client,
+    user_id: Uuid,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let mut params = SqlParams::default();
+    let user = params.push(user_id);
+    let conditions = vec![
+        VISIBLE_FILTER.to_string(),
+        format!(
+            "EXISTS (SELECT 1 FROM post_bookmarks b WHERE b.post_id = p.id AND b.user_id = {})",
+            user
+        ),
+    ];
+
+    list_posts(client, conditions, params, options).await
+}
+
+/// List published posts written by `username`
+pub async fn get_posts_by_author(
+    client: &Client,
+    username: &str,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let mut params = SqlParams::default();
+    let author = params.push(username.to_string());
+    let conditions = vec![
+        PUBLISHED_FILTER.to_string(),
+        format!("u.username = {}", author),
+    ];
+
+    list_posts(client, conditions, params, options).await
+}
+
+/// List posts written by `author_id` in any state except deleted,
+/// optionally limited to one status
+pub async fn get_own_posts(
+    client: &Client,
+    author_id: Uuid,
+    status: Option<PostStatus>,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let mut params = SqlParams::default();
+    let mut conditions = vec![
+        "p.deleted_at IS NULL".to_string(),
+        format!("p.created_by = {}", params.push(author_id)),
+    ];
+    if let Some(status) = status {
+        conditions.push(format!("p.status = {}", params.push(status.as_str())));
+    }
+
+    list_posts(client, conditions, params, options).await
+}
+
+/// List soft-deleted posts, optionally only those written by `author_id`
+pub async fn get_trashed_posts(
+    client: &Client,
+    author_id: Option<Uuid>,
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let mut params = SqlParams::default();
+    let mut conditions = vec!["p.deleted_at IS NOT NULL".to_string()];
+    if let Some(author_id) = author_id {
+        conditions.push(format!("p.created_by = {}", params.push(author_id)));
+    }
+
+    list_posts(client, conditions, params, options).await
+}
+
+/// The parts of a published post needed to build its public URL
+pub struct PostLocation {
+    pub username: String,
+    pub slug: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+pub async fn count_published_posts(client: &Client) -> Result<i64, tokio_postgres::Error> {
+    let query = format!("SELECT COUNT(*) FROM posts p WHERE {}", PUBLISHED_FILTER);
+    Ok(client.query_one(&query, &[]).await?.get(0))
+}
+
+/// List URL parts of published posts in a stable order, oldest first
+pub async fn get_post_locations(
+    client: &Client,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<PostLocation>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT u.username, p.slug, p.updated_at
+         FROM posts p
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE {}
+         ORDER BY p.created_at, p.id
+         LIMIT $1 OFFSET $2",
+        PUBLISHED_FILTER
+    );
+    let rows = client.query(&query, &[&limit, &offset]).await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| PostLocation {
+            username: row.get(0),
+            slug: row.get(1),
+            updated_at: row.get(2),
+        })
+        .collect())
 }
 
 pub async fn get_random_posts(
     client: &Client,
     limit: i64,
 ) -> Result<Vec<Post>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM posts p
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE {}
+         ORDER BY RANDOM()
+         LIMIT $1",
+        LIST_COLUMNS, PUBLISHED_FILTER
+    );
+    let rows = client.query(&query, &[&limit]).await?;
+
+    let mut posts: Vec<Post> = rows.iter().map(Post::from).collect();
+    fetch_tags_for_posts(client, &mut posts).await?;
+
+    Ok(posts)
+}
+
+/// List published posts from the precomputed trending ranking of `window`
+pub async fn get_trending_posts(
+    client: &Client,
+    window: TrendingWindow,
+    offset: i64,
+    limit: i64,
+) -> Result<(Vec<Post>, i64), tokio_postgres::Error> {
+    let from = format!(
+        "FROM trending_posts tp
+         INNER JOIN posts p ON tp.post_id = p.id
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE tp.period = $1 AND {}",
+        PUBLISHED_FILTER
+    );
+    let total: i64 = client
+        .query_one(&format!("SELECT COUNT(*) {}", from), &[&window.key()])
+        .await?
+        .get(0);
+
+    let query = format!(
+        "SELECT {} {} ORDER BY tp.score DESC, p.id LIMIT $2 OFFSET $3",
+        LIST_COLUMNS, from
+    );
     let rows = client
-        .query(
-            "SELECT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url,
-                    p.created_at, p.updated_at, p.deleted_at, p.published,
-                    p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p
-             INNER JOIN users u ON p.created_by = u.id
-             WHERE p.published = true AND p.deleted_at IS NULL
-             ORDER BY RANDOM()
-             LIMIT $1",
-            &[&limit],
+        .query(&query, &[&window.key(), &limit, &offset])
+        .await?;
+
+    let mut posts: Vec<Post> = rows.iter().map(Post::from).collect();
+    fetch_tags_for_posts(client, &mut posts).await?;
+
+    Ok((posts, total))
+}
+
+/// Score weight of each tag shared with the source post
+const RELATED_TAG_WEIGHT: f64 = 3.0;
+/// Score bonus for posts by the same author
+const RELATED_AUTHOR_WEIGHT: f64 = 1.0;
+/// Score weight of trigram title similarity (0..1)
+const RELATED_TITLE_WEIGHT: f64 = 2.0;
+
+/// Whether the `pg_trgm` extension needed for title similarity is installed
+pub async fn trigram_available(client: &Client) -> Result<bool, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'pg_trgm')",
+            &[],
         )
         .await?;
+    Ok(row.get(0))
+}
+
+/// List published posts related to `post_id`, best match first.
+///
+/// Candidates share a tag or the author with the source post, or with
+/// `title_similarity` have a similar title according to `pg_trgm`.
+pub async fn get_related_posts(
+    client: &Client,
+    post_id: Uuid,
+    limit: i64,
+    title_similarity: bool,
+) -> Result<Vec<Post>, tokio_postgres::Error> {
+    let (similar_title, similarity_score) = if title_similarity {
+        (
+            " OR p.title % src.title",
+            format!(
+                " + similarity(p.title, src.title) * {}",
+                RELATED_TITLE_WEIGHT
+            ),
+        )
+    } else {
+        ("", String::new())
+    };
+    let query = format!(
+        "SELECT {columns}
+         FROM posts p
+         INNER JOIN users u ON p.created_by = u.id
+         CROSS JOIN (SELECT created_by, title FROM posts WHERE id = $1) src
+         CROSS JOIN LATERAL (
+             SELECT COUNT(*) AS shared FROM posts_to_tags ptt
+             WHERE ptt.post_id = p.id
+               AND ptt.tag_id IN (SELECT tag_id FROM posts_to_tags WHERE post_id = $1)
+         ) st
+         WHERE {filter} AND p.id <> $1
+           AND (st.shared > 0 OR p.created_by = src.created_by{similar_title})
+         ORDER BY st.shared * {tag_weight}
+                  + CASE WHEN p.created_by = src.created_by THEN {author_weight} ELSE 0 END
+                  {similarity_score} DESC,
+                  p.created_at DESC, p.id DESC
+         LIMIT $2",
+        columns = LIST_COLUMNS,
+        filter = PUBLISHED_FILTER,
+        tag_weight = RELATED_TAG_WEIGHT,
+        author_weight = RELATED_AUTHOR_WEIGHT,
+    );
+    let rows = client.query(&query, &[&post_id, &limit]).await?;
 
     let mut posts: Vec<Post> = rows.iter().map(Post::from).collect();
     fetch_tags_for_posts(client, &mut posts).await?;
@@ -156,19 +720,24 @@ pub async fn get_random_posts(
     Ok(posts)
 }
 
+/// Get a published or unlisted post by its author and slug. The author
+/// (`viewer`) can also open their own drafts, scheduled and archived posts.
 pub async fn get_post_by_username_and_slug(
     client: &Client,
     username: &str,
     slug: &str,
+    viewer: Option<Uuid>,
 ) -> Result<Option<Post>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM posts p
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE u.username = $1 AND p.slug = $2
+           AND (({}) OR (p.created_by = $3 AND p.deleted_at IS NULL))",
+        POST_COLUMNS, VISIBLE_FILTER
+    );
     let row = client
-        .query_opt(
-            "SELECT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.published, p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p
-             INNER JOIN users u ON p.created_by = u.id
-             WHERE u.username = $1 AND p.slug = $2 AND p.published = true AND p.deleted_at IS NULL",
-            &[&username, &slug],
-        )
+        .query_opt(&query, &[&username, &slug, &viewer])
         .await?;
 
     match row {
@@ -196,77 +765,689 @@ pub async fn get_post_by_username_and_slug(
     }
 }
 
+/// Find the current username and slug of a post that used to be reachable as
+/// `username`/`slug`, following renamed authors and changed slugs. Only posts
+/// `viewer` may open are resolved.
+pub async fn resolve_moved_post(
+    client: &Client,
+    username: &str,
+    slug: &str,
+    viewer: Option<Uuid>,
+) -> Result<Option<(String, String)>, tokio_postgres::Error> {
+    let query = format!(
+        "WITH author AS (
+             SELECT id, 0 AS rank FROM users WHERE username = $1
+             UNION ALL
+             SELECT user_id, 1 FROM username_history WHERE username = $1
+             ORDER BY rank LIMIT 1
+         ),
+         target AS (
+             SELECT p.id, 0 AS rank FROM posts p, author a
+             WHERE p.created_by = a.id AND p.slug = $2
+             UNION ALL
+             SELECT h.post_id, 1 FROM post_slug_history h, author a
+             WHERE h.created_by = a.id AND h.slug = $2
+             ORDER BY rank LIMIT 1
+         )
+         SELECT u.username, p.slug
+         FROM target t
+         INNER JOIN posts p ON p.id = t.id
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE ({}) OR (p.created_by = $3 AND p.deleted_at IS NULL)",
+        VISIBLE_FILTER
+    );
+    let row = client
+        .query_opt(&query, &[&username, &slug, &viewer])
+        .await?;
+    Ok(row.map(|r| (r.get(0), r.get(1))))
+}
+
 pub async fn get_posts_by_tag(
     client: &Client,
     tag_name: &str,
-    offset: i64,
-    limit: i64,
-    search: Option<&str>,
-    order_by: Option<&str>,
-    order_direction: Option<&OrderDirection>,
-) -> Result<(Vec<Post>, i64), tokio_postgres::Error> {
-    let order_field = validate_order_field(order_by);
-    let order_dir = get_order_dir(order_direction);
-    let search_param = search.map(|s| format!("%{}%", escape_like_pattern(s)));
-
-    // Get total count
-    let total: i64 = if let Some(ref search_val) = search_param {
-        client
-            .query_one(
-                "SELECT COUNT(DISTINCT p.id) FROM posts p
-                 INNER JOIN users u ON p.created_by = u.id
-                 INNER JOIN posts_to_tags ptt ON p.id = ptt.post_id
-                 INNER JOIN tags t ON ptt.tag_id = t.id
-                 WHERE t.name = $1 AND p.published = true AND p.deleted_at IS NULL AND (p.title ILIKE $2 ESCAPE '\\' OR p.body ILIKE $2 ESCAPE '\\' OR u.username ILIKE $2 ESCAPE '\\')",
-                &[&tag_name, search_val],
-            )
-            .await?
-            .get(0)
+    options: ListOptions<'_>,
+) -> Result<PostPage, tokio_postgres::Error> {
+    let mut params = SqlParams::default();
+    let mut conditions = vec![PUBLISHED_FILTER.to_string()];
+    let tag = params.push(tag_name.to_string());
+    conditions.push(format!(
+        "EXISTS (SELECT 1 FROM posts_to_tags ptt INNER JOIN tags t ON ptt.tag_id = t.id
+                 WHERE ptt.post_id = p.id AND t.name = {})",
+        tag
+    ));
+
+    list_posts(client, conditions, params, options).await
+}
+
+/// Latest `updated_at` of the posts tagged `tag_name` or written by `username`,
+/// or of all posts, in any state. Deleting, unpublishing or publishing a post
+/// bumps `updated_at`, so this also changes when a post leaves or joins a feed.
+pub async fn get_latest_change(
+    client: &Client,
+    tag_name: Option<&str>,
+    username: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, tokio_postgres::Error> {
+    let row = client
+        .query_one(
+            "SELECT MAX(p.updated_at)
+             FROM posts p
+             INNER JOIN users u ON p.created_by = u.id
+             WHERE ($1::text IS NULL OR EXISTS (
+                       SELECT 1 FROM posts_to_tags ptt INNER JOIN tags t ON ptt.tag_id = t.id
+                       WHERE ptt.post_id = p.id AND t.name = $1))
+               AND ($2::text IS NULL OR u.username = $2)",
+            &[&tag_name, &username],
+        )
+        .await?;
+    Ok(row.get(0))
+}
+
+/// Input for creating a post
+pub struct NewPost<'a> {
+    pub title: &'a str,
+    pub body: Option<&'a str>,
+    pub slug: Option<&'a str>,
+    pub photo_url: Option<&'a str>,
+    pub status: PostStatus,
+    /// Required when `status` is scheduled
+    pub publish_at: Option<DateTime<Utc>>,
+    pub tags: &'a [String],
+    /// Author-supplied summary; generated from the body when absent or blank
+    pub excerpt: Option<&'a str>,
+}
+
+/// Partial update for an existing post; `None` fields are left unchanged
+pub struct PostChanges<'a> {
+    pub title: Option<&'a str>,
+    /// `Some(None)` clears the body
+    pub body: Option<Option<&'a str>>,
+    pub slug: Option<&'a str>,
+    /// `Some(None)` clears the photo
+    pub photo_url: Option<Option<&'a str>>,
+    pub status: Option<PostStatus>,
+    /// Required when `status` changes to scheduled
+    pub publish_at: Option<DateTime<Utc>>,
+    pub tags: Option<&'a [String]>,
+    /// A blank excerpt goes back to one generated from the body
+    pub excerpt: Option<&'a str>,
+}
+
+/// Excerpt stored for `body` when the author has not written one
+fn generated_excerpt(body: Option<&str>) -> Option<String> {
+    body.map(|b| markdown::excerpt(b, EXCERPT_LENGTH))
+}
+
+/// Author-supplied excerpt with surrounding whitespace removed, if not blank
+fn custom_excerpt(excerpt: &str) -> Option<&str> {
+    Some(excerpt.trim()).filter(|e| !e.is_empty())
+}
+
+/// Convert a title into a URL-safe slug matching `^[a-z0-9-]+$`
+fn slugify(title: &str) -> String {
+    let mut slug = String::with_capacity(title.len());
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug: String = slug.chars().take(80).collect();
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("post")
     } else {
-        client
-            .query_one(
-                "SELECT COUNT(DISTINCT p.id) FROM posts p
-                 INNER JOIN posts_to_tags ptt ON p.id = ptt.post_id
-                 INNER JOIN tags t ON ptt.tag_id = t.id
-                 WHERE t.name = $1 AND p.published = true AND p.deleted_at IS NULL",
-                &[&tag_name],
+        slug.to_string()
+    }
+}
+
+/// Find a slug that is unused by the author, appending `-2`, `-3`, ... when taken.
+/// `exclude_post` lets a post keep its own slug on update.
+async fn unique_slug(
+    tx: &Transaction<'_>,
+    author_id: Uuid,
+    base: &str,
+    exclude_post: Option<Uuid>,
+) -> Result<String, tokio_postgres::Error> {
+    let prefix = format!("{}-%", escape_like_pattern(base));
+    let rows = tx
+        .query(
+            "SELECT slug FROM posts
+             WHERE created_by = $1 AND (slug = $2 OR slug LIKE $3 ESCAPE '\\')
+               AND ($4::uuid IS NULL OR id <> $4)",
+            &[&author_id, &base, &prefix, &exclude_post],
+        )
+        .await?;
+
+    let taken: HashSet<String> = rows.iter().map(|r| r.get(0)).collect();
+    if !taken.contains(base) {
+        return Ok(base.to_string());
+    }
+
+    let mut n = 2;
+    loop {
+        let candidate = format!("{}-{}", base, n);
+        if !taken.contains(&candidate) {
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// Replace the tags of a post, creating missing tags by name
+pub async fn set_post_tags(
+    tx: &Transaction<'_>,
+    post_id: Uuid,
+    tags: &[String],
+) -> Result<(), tokio_postgres::Error> {
+    tx.execute("DELETE FROM posts_to_tags WHERE post_id = $1", &[&post_id])
+        .await?;
+
+    if tags.is_empty() {
+        return Ok(());
+    }
+
+    tx.execute(
+        "INSERT INTO tags (name, created_at)
+         SELECT name, NOW() FROM unnest($1::text[]) AS name
+         ON CONFLICT (name) DO NOTHING",
+        &[&tags],
+    )
+    .await?;
+    tx.execute(
+        "INSERT INTO posts_to_tags (post_id, tag_id)
+         SELECT $1, id FROM tags WHERE name = ANY($2)",
+        &[&post_id, &tags],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Remember the previous slug of a post so links to it keep resolving.
+/// A history entry for the new slug is dropped since the live slug now owns it.
+async fn record_slug_change(
+    tx: &Transaction<'_>,
+    author_id: Uuid,
+    post_id: Uuid,
+    old_slug: &str,
+    new_slug: &str,
+) -> Result<(), tokio_postgres::Error> {
+    tx.execute(
+        "DELETE FROM post_slug_history WHERE created_by = $1 AND slug = $2",
+        &[&author_id, &new_slug],
+    )
+    .await?;
+    tx.execute(
+        "INSERT INTO post_slug_history (created_by, slug, post_id, created_at)
+         VALUES ($1, $2, $3, NOW())
+         ON CONFLICT (created_by, slug) DO UPDATE
+         SET post_id = EXCLUDED.post_id, created_at = EXCLUDED.created_at",
+        &[&author_id, &old_slug, &post_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Recompute the weighted full-text `search_vector` of the given posts:
+/// title (A) > body (B) > tags (C) > author username (D)
+pub async fn refresh_search_vectors(
+    tx: &Transaction<'_>,
+    post_ids: &[Uuid],
+) -> Result<(), tokio_postgres::Error> {
+    let query = format!(
+        "UPDATE posts p SET search_vector =
+             setweight(to_tsvector('{cfg}', COALESCE(p.title, '')), 'A') ||
+             setweight(to_tsvector('{cfg}', COALESCE(p.body, '')), 'B') ||
+             setweight(to_tsvector('{cfg}', COALESCE(
+                 (SELECT string_agg(t.name, ' ') FROM posts_to_tags ptt
+                  INNER JOIN tags t ON ptt.tag_id = t.id WHERE ptt.post_id = p.id), '')), 'C') ||
+             setweight(to_tsvector('{cfg}', u.username), 'D')
+         FROM users u
+         WHERE p.id = ANY($1) AND u.id = p.created_by",
+        cfg = SEARCH_CONFIG
+    );
+    tx.execute(&query, &[&post_ids]).await?;
+    Ok(())
+}
+
+/// Get a post by id regardless of its published or deleted state
+pub async fn get_post_by_id(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM posts p
+         INNER JOIN users u ON p.created_by = u.id
+         WHERE p.id = $1",
+        POST_COLUMNS
+    );
+    let row = client.query_opt(&query, &[&id]).await?;
+
+    match row {
+        Some(row) => {
+            let mut post = Post::from_full(&row);
+            fetch_tags_for_posts(client, std::slice::from_mut(&mut post)).await?;
+            Ok(Some(post))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Check that a post exists, is published or unlisted and not deleted
+pub async fn post_is_visible(
+    client: &Client,
+    post_id: Uuid,
+) -> Result<bool, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT 1 FROM posts p WHERE p.id = $1 AND {}",
+        VISIBLE_FILTER
+    );
+    let row = client.query_opt(&query, &[&post_id]).await?;
+    Ok(row.is_some())
+}
+
+/// Drop posts that are no longer published, e.g. from a cached list
+pub async fn retain_published(
+    client: &Client,
+    posts: &mut Vec<Post>,
+) -> Result<(), tokio_postgres::Error> {
+    if posts.is_empty() {
+        return Ok(());
+    }
+    let ids: Vec<Uuid> = posts.iter().map(|p| p.id).collect();
+    let query = format!(
+        "SELECT p.id FROM posts p WHERE p.id = ANY($1) AND {}",
+        PUBLISHED_FILTER
+    );
+    let published: HashSet<Uuid> = client
+        .query(&query, &[&ids])
+        .await?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    posts.retain(|p| published.contains(&p.id));
+    Ok(())
+}
+
+/// Get the author of a post that has not been deleted
+pub async fn get_post_owner(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT created_by FROM posts WHERE id = $1 AND deleted_at IS NULL",
+            &[&id],
+        )
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Get the author of a soft-deleted post
+pub async fn get_trashed_post_owner(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT created_by FROM posts WHERE id = $1 AND deleted_at IS NOT NULL",
+            &[&id],
+        )
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Create a post, its tag links and its first revision in a single transaction
+pub async fn create_post(
+    client: &mut Client,
+    author_id: Uuid,
+    input: NewPost<'_>,
+) -> Result<Post, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let base = input
+        .slug
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| slugify(input.title));
+    let custom = input.excerpt.and_then(custom_excerpt);
+    let excerpt = match custom {
+        Some(e) => Some(e.to_string()),
+        None => generated_excerpt(input.body),
+    };
+
+    // A concurrent create may take the chosen slug first; the insert then
+    // skips and the next free slug is picked
+    let query = format!(
+        "WITH p AS (
+             INSERT INTO posts (id, title, body, created_by, slug, photo_url, created_at, updated_at,
+                                status, publish_at, view_count, like_count, bookmark_count, comment_count,
+                                excerpt, excerpt_custom)
+             VALUES ($1, $2, $3, $4, $5, $6, NOW(), NOW(), $7,
+                     CASE $7 WHEN 'scheduled' THEN $8
+                             WHEN 'published' THEN NOW()
+                             WHEN 'unlisted' THEN NOW() END,
+                     0, 0, 0, 0, $9, $10)
+             ON CONFLICT (created_by, slug) DO NOTHING
+             RETURNING *
+         )
+         SELECT {}
+         FROM p
+         INNER JOIN users u ON p.created_by = u.id",
+        POST_COLUMNS
+    );
+    let id = Uuid::new_v4();
+    let row = loop {
+        let slug = unique_slug(&tx, author_id, &base, None).await?;
+        let row = tx
+            .query_opt(
+                &query,
+                &[
+                    &id,
+                    &input.title,
+                    &input.body,
+                    &author_id,
+                    &slug,
+                    &input.photo_url,
+                    &input.status.as_str(),
+                    &input.publish_at,
+                    &excerpt,
+                    &custom.is_some(),
+                ],
             )
-            .await?
-            .get(0)
+            .await?;
+        if let Some(row) = row {
+            break row;
+        }
     };
+    set_post_tags(&tx, id, input.tags).await?;
+    refresh_search_vectors(&tx, &[id]).await?;
+    record_revision(&tx, id, author_id).await?;
 
-    // Build and execute main query
-    let query = if search_param.is_some() {
-        format!(
-            "SELECT DISTINCT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.published, p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p INNER JOIN users u ON p.created_by = u.id
-             INNER JOIN posts_to_tags ptt ON p.id = ptt.post_id
-             INNER JOIN tags t ON ptt.tag_id = t.id
-             WHERE t.name = $1 AND p.published = true AND p.deleted_at IS NULL AND (p.title ILIKE $2 ESCAPE '\\' OR p.body ILIKE $2 ESCAPE '\\' OR u.username ILIKE $2 ESCAPE '\\')
-             ORDER BY p.{} {} LIMIT $3 OFFSET $4",
-            order_field, order_dir
-        )
-    } else {
-        format!(
-            "SELECT DISTINCT p.id, p.title, p.body, p.created_by, p.slug, p.photo_url, p.created_at, p.updated_at, p.deleted_at, p.published, p.view_count, p.like_count, p.bookmark_count, u.id, u.username, u.image
-             FROM posts p INNER JOIN users u ON p.created_by = u.id
-             INNER JOIN posts_to_tags ptt ON p.id = ptt.post_id
-             INNER JOIN tags t ON ptt.tag_id = t.id
-             WHERE t.name = $1 AND p.published = true AND p.deleted_at IS NULL ORDER BY p.{} {} LIMIT $2 OFFSET $3",
-            order_field, order_dir
+    let mut post = Post::from_full(&row);
+    fetch_tags_for_posts(&tx, std::slice::from_mut(&mut post)).await?;
+
+    tx.commit().await?;
+
+    Ok(post)
+}
+
+/// Update a post owned by `author_id`, recording a revision if the title, body
+/// or tags changed.
+/// Returns `None` if the post does not exist, is deleted, or belongs to someone else.
+pub async fn update_post(
+    client: &mut Client,
+    author_id: Uuid,
+    id: Uuid,
+    changes: PostChanges<'_>,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let mut tx = client.transaction().await?;
+
+    let Some(row) = tx
+        .query_opt(
+            "SELECT slug, body, excerpt_custom FROM posts
+             WHERE id = $1 AND created_by = $2 AND deleted_at IS NULL FOR UPDATE",
+            &[&id, &author_id],
         )
+        .await?
+    else {
+        return Ok(None);
     };
+    let old_slug: String = row.get(0);
+    let old_body: Option<String> = row.get(1);
+    let is_custom: bool = row.get(2);
 
-    let rows = if let Some(ref search_val) = search_param {
-        client
-            .query(&query, &[&tag_name, search_val, &limit, &offset])
-            .await?
-    } else {
-        client.query(&query, &[&tag_name, &limit, &offset]).await?
+    // A new excerpt replaces the stored one; a generated excerpt also follows
+    // body edits. `excerpt_custom` is set whenever the excerpt changes, which
+    // may be to none when there is no body to generate it from.
+    let body = changes.body.unwrap_or(old_body.as_deref());
+    let (excerpt, excerpt_custom) = match changes.excerpt.map(custom_excerpt) {
+        Some(Some(e)) => (Some(e.to_string()), Some(true)),
+        Some(None) => (generated_excerpt(body), Some(false)),
+        None if changes.body.is_some() && !is_custom => (generated_excerpt(body), Some(false)),
+        None => (None, None),
     };
 
-    let mut posts: Vec<Post> = rows.iter().map(Post::from).collect();
-    fetch_tags_for_posts(client, &mut posts).await?;
+    // A concurrent change may take the chosen slug first; the update is then
+    // rolled back to a savepoint and retried with the next free slug
+    let slug = loop {
+        let slug = match changes.slug {
+            Some(s) => Some(unique_slug(&tx, author_id, &s.to_lowercase(), Some(id)).await?),
+            None => None,
+        };
+        let savepoint = tx.savepoint("post_slug").await?;
+        match savepoint
+            .execute(
+                "UPDATE posts SET
+                     title = COALESCE($2, title),
+                     body = CASE WHEN $10 THEN $3 ELSE body END,
+                     slug = COALESCE($4, slug),
+                     photo_url = CASE WHEN $11 THEN $5 ELSE photo_url END,
+                     status = COALESCE($6, status),
+                     -- Keep a past publication time; otherwise published now, at the
+                     -- scheduled time, or not at all
+                     publish_at = CASE
+                         WHEN $6::text IS NULL THEN publish_at
+                         WHEN $6 = 'scheduled' THEN $7
+                         WHEN publish_at <= NOW() THEN publish_at
+                         WHEN $6 IN ('published', 'unlisted') THEN NOW()
+                     END,
+                     excerpt = CASE WHEN $9::boolean IS NULL THEN excerpt ELSE $8 END,
+                     excerpt_custom = COALESCE($9, excerpt_custom),
+                     updated_at = NOW()
+                 WHERE id = $1",
+                &[
+                    &id,
+                    &changes.title,
+                    &changes.body.flatten(),
+                    &slug,
+                    &changes.photo_url.flatten(),
+                    &changes.status.map(PostStatus::as_str),
+                    &changes.publish_at,
+                    &excerpt,
+                    &excerpt_custom,
+                    &changes.body.is_some(),
+                    &changes.photo_url.is_some(),
+                ],
+            )
+            .await
+        {
+            Ok(_) => {
+                savepoint.commit().await?;
+                break slug;
+            }
+            Err(e) if is_unique_violation(&e, "posts_created_by_slug_key") => {
+                savepoint.rollback().await?;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    if let Some(slug) = slug.as_deref().filter(|s| *s != old_slug) {
+        record_slug_change(&tx, author_id, id, &old_slug, slug).await?;
+    }
+    if let Some(tags) = changes.tags {
+        set_post_tags(&tx, id, tags).await?;
+    }
+    refresh_search_vectors(&tx, &[id]).await?;
+    record_revision(&tx, id, author_id).await?;
 
-    Ok((posts, total))
+    tx.commit().await?;
+
+    get_post_by_id(client, id).await
+}
+
+/// Soft-delete a post owned by `author_id` by setting `deleted_at`.
+/// Returns `None` if the post does not exist, is already deleted, or belongs to someone else.
+pub async fn delete_post(
+    client: &Client,
+    author_id: Uuid,
+    id: Uuid,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let updated = client
+        .execute(
+            "UPDATE posts SET deleted_at = NOW(), updated_at = NOW()
+             WHERE id = $1 AND created_by = $2 AND deleted_at IS NULL",
+            &[&id, &author_id],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    get_post_by_id(client, id).await
+}
+
+/// Undo the soft delete of a post, keeping its status.
+/// Returns `None` if the post does not exist or is not deleted.
+pub async fn restore_post(
+    client: &Client,
+    id: Uuid,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let updated = client
+        .execute(
+            "UPDATE posts SET deleted_at = NULL, updated_at = NOW()
+             WHERE id = $1 AND deleted_at IS NOT NULL",
+            &[&id],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(None);
+    }
+
+    get_post_by_id(client, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_escape_html_and_mark_matches() {
+        let headline = "<script>alert(1)</script> \u{2}rust\u{3} & <img onerror=\"x\">";
+        assert_eq!(
+            mark_snippet(headline),
+            "&lt;script&gt;alert(1)&lt;/script&gt; <mark>rust</mark> &amp; \
+             &lt;img onerror=&quot;x&quot;&gt;"
+        );
+        assert_eq!(mark_snippet("\u{3}a \u{2}b \u{2}c"), "a <mark>b c</mark>");
+    }
+
+    fn cursor(value: CursorValue, backward: bool) -> Cursor {
+        Cursor {
+            value,
+            id: Uuid::new_v4(),
+            backward,
+        }
+    }
+
+    fn round_trip(cursor: &Cursor, order_by: &str, dir: OrderDirection, searching: bool) {
+        let encoded = cursor.encode(
+            validate_order_field(Some(order_by), searching),
+            get_order_dir(Some(&dir)),
+        );
+        let decoded = decode_cursor(&encoded, Some(order_by), Some(&dir), searching)
+            .unwrap_or_else(|| panic!("cursor for {} did not decode", order_by));
+        assert_eq!(decoded.value, cursor.value);
+        assert_eq!(decoded.id, cursor.id);
+        assert_eq!(decoded.backward, cursor.backward);
+    }
+
+    #[test]
+    fn cursors_round_trip_for_every_order_field() {
+        let time = DateTime::parse_from_rfc3339("2024-05-06T07:08:09.123456Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        round_trip(
+            &cursor(CursorValue::Id, false),
+            "id",
+            OrderDirection::Asc,
+            false,
+        );
+        round_trip(
+            &cursor(CursorValue::Text(String::from("Ünïcode, \"quotes\"")), true),
+            "title",
+            OrderDirection::Desc,
+            false,
+        );
+        for field in ["created_at", "updated_at", "published_at"] {
+            round_trip(
+                &cursor(CursorValue::Time(time), false),
+                field,
+                OrderDirection::Desc,
+                false,
+            );
+        }
+        for field in ["view_count", "like_count", "bookmark_count"] {
+            round_trip(
+                &cursor(CursorValue::Count(42), true),
+                field,
+                OrderDirection::Asc,
+                false,
+            );
+        }
+        round_trip(
+            &cursor(CursorValue::Rank(0.0607927), false),
+            "relevance",
+            OrderDirection::Desc,
+            true,
+        );
+    }
+
+    #[test]
+    fn rejects_cursors_for_another_ordering() {
+        let encoded = cursor(CursorValue::Count(3), false).encode("like_count", "DESC");
+        let desc = OrderDirection::Desc;
+        assert!(decode_cursor(&encoded, Some("like_count"), Some(&desc), false).is_some());
+        assert!(decode_cursor(&encoded, Some("view_count"), Some(&desc), false).is_none());
+        assert!(
+            decode_cursor(
+                &encoded,
+                Some("like_count"),
+                Some(&OrderDirection::Asc),
+                false
+            )
+            .is_none()
+        );
+        // Defaults to `published_at` descending
+        assert!(decode_cursor(&encoded, None, None, false).is_none());
+
+        // Relevance is only an ordering while searching
+        let encoded = cursor(CursorValue::Rank(0.5), false).encode("relevance", "DESC");
+        assert!(decode_cursor(&encoded, Some("relevance"), None, true).is_some());
+        assert!(decode_cursor(&encoded, Some("relevance"), None, false).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        let encode = |json: &str| URL_SAFE_NO_PAD.encode(json);
+        let id = Uuid::new_v4();
+        for encoded in [
+            String::new(),
+            String::from("not base64!"),
+            String::from("%%%"),
+            encode("not json"),
+            encode(r#"{"f":"like_count","d":"DESC"}"#),
+            encode(&format!(
+                r#"{{"f":"like_count","d":"DESC","v":"many","id":"{}"}}"#,
+                id
+            )),
+            encode(&format!(
+                r#"{{"f":"like_count","d":"DESC","v":null,"id":"{}"}}"#,
+                id
+            )),
+            encode(&format!(
+                r#"{{"f":"created_at","d":"DESC","v":"yesterday","id":"{}"}}"#,
+                id
+            )),
+            encode(r#"{"f":"like_count","d":"DESC","v":"1","id":"not-a-uuid"}"#),
+        ] {
+            assert!(
+                decode_cursor(&encoded, Some("like_count"), None, false).is_none()
+                    && decode_cursor(&encoded, Some("created_at"), None, false).is_none(),
+                "{} decoded",
+                encoded
+            );
+        }
+    }
 }
@@ -0,0 +1,41 @@
+use crate::database::DbPool;
+use std::time::Duration;
+use tokio_postgres::Client;
+
+/// Publish scheduled posts whose `publish_at` has passed, returning how many changed.
+/// `updated_at` is bumped so feeds and sitemaps pick the posts up.
+pub async fn publish_due_posts(client: &Client) -> Result<u64, tokio_postgres::Error> {
+    client
+        .execute(
+            "UPDATE posts SET status = 'published', updated_at = NOW()
+             WHERE status = 'scheduled' AND publish_at <= NOW() AND deleted_at IS NULL",
+            &[],
+        )
+        .await
+}
+
+/// Promote due scheduled posts every `interval`, starting immediately
+pub fn spawn_publisher(pool: DbPool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let client = match pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to get a connection to publish scheduled posts: {}",
+                        e
+                    );
+                    continue;
+                }
+            };
+            match publish_due_posts(&client).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Published {} scheduled posts", n),
+                Err(e) => tracing::error!("Failed to publish scheduled posts: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,125 @@
+use crate::models::post::Post;
+use crate::services::trending;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+/// A per-user action on a post backed by a join table and a counter on `posts`
+#[derive(Clone, Copy)]
+pub enum Reaction {
+    Like,
+    Bookmark,
+}
+
+impl Reaction {
+    fn table(self) -> &'static str {
+        match self {
+            Reaction::Like => "post_likes",
+            Reaction::Bookmark => "post_bookmarks",
+        }
+    }
+
+    /// `post_events.kind` recorded for trending
+    fn event_kind(self) -> &'static str {
+        match self {
+            Reaction::Like => "like",
+            Reaction::Bookmark => "bookmark",
+        }
+    }
+
+    fn counter(self) -> &'static str {
+        match self {
+            Reaction::Like => "like_count",
+            Reaction::Bookmark => "bookmark_count",
+        }
+    }
+}
+
+/// Add or remove a reaction, adjusting the post counter only when the row changed.
+/// Both operations are idempotent.
+pub async fn set_reaction(
+    client: &mut Client,
+    reaction: Reaction,
+    post_id: Uuid,
+    user_id: Uuid,
+    active: bool,
+) -> Result<(), tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    // Both return when the reaction was made, so a removal can be recorded at
+    // that time and cancel exactly the trending weight the reaction added
+    let (change, delta) = if active {
+        (
+            format!(
+                "INSERT INTO {} (post_id, user_id, created_at) VALUES ($1, $2, NOW())
+                 ON CONFLICT DO NOTHING
+                 RETURNING created_at",
+                reaction.table()
+            ),
+            "+ 1",
+        )
+    } else {
+        (
+            format!(
+                "DELETE FROM {} WHERE post_id = $1 AND user_id = $2 RETURNING created_at",
+                reaction.table()
+            ),
+            "- 1",
+        )
+    };
+
+    let changed = tx.query_opt(&change, &[&post_id, &user_id]).await?;
+    if let Some(row) = changed {
+        let query = format!(
+            "UPDATE posts SET {counter} = GREATEST({counter} {delta}, 0) WHERE id = $1",
+            counter = reaction.counter(),
+            delta = delta
+        );
+        tx.execute(&query, &[&post_id]).await?;
+
+        let count: i32 = if active { 1 } else { -1 };
+        let reacted_at: DateTime<Utc> = row.get(0);
+        trending::record_event(&tx, post_id, reaction.event_kind(), count, reacted_at).await?;
+    }
+
+    tx.commit().await
+}
+
+/// Set `liked_by_me` and `bookmarked_by_me` on each post for the viewer in one query.
+/// Leaves the flags unset for anonymous viewers.
+pub async fn fill_viewer_flags(
+    client: &Client,
+    viewer: Option<Uuid>,
+    posts: &mut [Post],
+) -> Result<(), tokio_postgres::Error> {
+    let Some(viewer) = viewer else {
+        return Ok(());
+    };
+    if posts.is_empty() {
+        return Ok(());
+    }
+
+    let post_ids: Vec<Uuid> = posts.iter().map(|p| p.id).collect();
+    let rows = client
+        .query(
+            "SELECT ids.id,
+                    EXISTS (SELECT 1 FROM post_likes l WHERE l.post_id = ids.id AND l.user_id = $2),
+                    EXISTS (SELECT 1 FROM post_bookmarks b WHERE b.post_id = ids.id AND b.user_id = $2)
+             FROM unnest($1::uuid[]) AS ids(id)",
+            &[&post_ids, &viewer],
+        )
+        .await?;
+
+    let flags: HashMap<Uuid, (bool, bool)> = rows
+        .iter()
+        .map(|row| (row.get(0), (row.get(1), row.get(2))))
+        .collect();
+    for post in posts {
+        let (liked, bookmarked) = flags.get(&post.id).copied().unwrap_or_default();
+        post.liked_by_me = Some(liked);
+        post.bookmarked_by_me = Some(bookmarked);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,226 @@
+use crate::markdown;
+use crate::models::post::{EXCERPT_LENGTH, Post};
+use crate::models::revision::{DiffLine, FieldChange, LineChange, PostRevision, RevisionDiff};
+use crate::models::tag::Tag;
+use crate::services::post::{get_post_by_id, refresh_search_vectors, set_post_tags};
+use similar::{ChangeTag, TextDiff};
+use std::time::{Duration, Instant};
+use tokio_postgres::{Client, Transaction};
+use uuid::Uuid;
+
+/// Longest time spent searching for a minimal body diff
+const DIFF_DEADLINE: Duration = Duration::from_secs(1);
+
+const REVISION_COLUMNS: &str =
+    "r.post_id, r.revision, r.title, r.body, r.tags, r.created_at, u.id, u.username, u.image";
+
+/// Snapshot the current title, body and tags of a post as its next revision.
+/// Nothing is recorded when they match the latest revision.
+///
+/// Callers must hold the post's row lock so concurrent edits cannot claim the
+/// same revision number.
+pub async fn record_revision(
+    tx: &Transaction<'_>,
+    post_id: Uuid,
+    user_id: Uuid,
+) -> Result<(), tokio_postgres::Error> {
+    tx.execute(
+        "WITH current AS (
+             SELECT p.title, p.body,
+                    ARRAY(SELECT t.name::text FROM posts_to_tags ptt
+                          INNER JOIN tags t ON ptt.tag_id = t.id
+                          WHERE ptt.post_id = p.id ORDER BY t.name) AS tags
+             FROM posts p WHERE p.id = $1
+         ),
+         latest AS (
+             SELECT revision, title, body, tags FROM post_revisions
+             WHERE post_id = $1 ORDER BY revision DESC LIMIT 1
+         )
+         INSERT INTO post_revisions (post_id, revision, title, body, tags, created_by, created_at)
+         SELECT $1, COALESCE((SELECT revision FROM latest), 0) + 1,
+                c.title, c.body, c.tags, $2, NOW()
+         FROM current c
+         WHERE NOT EXISTS (
+             SELECT 1 FROM latest l
+             WHERE l.title = c.title AND l.body IS NOT DISTINCT FROM c.body AND l.tags = c.tags
+         )",
+        &[&post_id, &user_id],
+    )
+    .await?;
+    Ok(())
+}
+
+/// List the revisions of a post, newest first
+pub async fn get_revisions(
+    client: &Client,
+    post_id: Uuid,
+) -> Result<Vec<PostRevision>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM post_revisions r
+         INNER JOIN users u ON r.created_by = u.id
+         WHERE r.post_id = $1
+         ORDER BY r.revision DESC",
+        REVISION_COLUMNS
+    );
+    let rows = client.query(&query, &[&post_id]).await?;
+    Ok(rows.iter().map(PostRevision::from).collect())
+}
+
+/// Get one revision of a post including its body
+pub async fn get_revision(
+    client: &Client,
+    post_id: Uuid,
+    revision: i32,
+) -> Result<Option<PostRevision>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT {}
+         FROM post_revisions r
+         INNER JOIN users u ON r.created_by = u.id
+         WHERE r.post_id = $1 AND r.revision = $2",
+        REVISION_COLUMNS
+    );
+    let row = client.query_opt(&query, &[&post_id, &revision]).await?;
+    Ok(row.as_ref().map(PostRevision::from))
+}
+
+/// Render a post as it was at `revision`, with its full body.
+/// Tags deleted since the revision was made are left out.
+pub async fn get_post_at_revision(
+    client: &Client,
+    post_id: Uuid,
+    revision: i32,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let Some(revision) = get_revision(client, post_id, revision).await? else {
+        return Ok(None);
+    };
+    let Some(mut post) = get_post_by_id(client, post_id).await? else {
+        return Ok(None);
+    };
+
+    let rows = client
+        .query(
+            "SELECT id, name, created_at FROM tags WHERE name = ANY($1) ORDER BY name",
+            &[&revision.tags],
+        )
+        .await?;
+    post.title = revision.title;
+    post.body = revision.body;
+    post.updated_at = revision.created_at;
+    post.tags = rows.iter().map(Tag::from).collect();
+
+    Ok(Some(post))
+}
+
+/// Compare two revisions: title and tag changes plus a line diff of the body
+/// with `context` unchanged lines around each hunk of the unified output.
+/// Long bodies take a while to diff, so call this off the async workers.
+pub fn diff_revisions(from: &PostRevision, to: &PostRevision, context: usize) -> RevisionDiff {
+    let old_body = from.body.as_deref().unwrap_or_default();
+    let new_body = to.body.as_deref().unwrap_or_default();
+    // Past the deadline the diff is coarser but still correct
+    let diff = TextDiff::configure()
+        .deadline(Instant::now() + DIFF_DEADLINE)
+        .diff_lines(old_body, new_body);
+
+    let unified = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(
+            &format!("revision {}", from.revision),
+            &format!("revision {}", to.revision),
+        )
+        .to_string();
+
+    let lines = diff
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            change: match change.tag() {
+                ChangeTag::Equal => LineChange::Equal,
+                ChangeTag::Insert => LineChange::Insert,
+                ChangeTag::Delete => LineChange::Delete,
+            },
+            old_line: change.old_index().map(|i| i + 1),
+            new_line: change.new_index().map(|i| i + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect();
+
+    RevisionDiff {
+        from: from.revision,
+        to: to.revision,
+        title: (from.title != to.title).then(|| FieldChange {
+            from: from.title.clone(),
+            to: to.title.clone(),
+        }),
+        tags_added: to
+            .tags
+            .iter()
+            .filter(|t| !from.tags.contains(t))
+            .cloned()
+            .collect(),
+        tags_removed: from
+            .tags
+            .iter()
+            .filter(|t| !to.tags.contains(t))
+            .cloned()
+            .collect(),
+        unified,
+        lines,
+    }
+}
+
+/// Restore the title, body and tags of a post owned by `author_id` from one of
+/// its revisions, recording the result as a new revision.
+/// Returns `None` if the post or the revision does not exist.
+pub async fn restore_revision(
+    client: &mut Client,
+    author_id: Uuid,
+    post_id: Uuid,
+    revision: i32,
+) -> Result<Option<Post>, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+
+    let exists = tx
+        .query_opt(
+            "SELECT 1 FROM posts WHERE id = $1 AND created_by = $2 AND deleted_at IS NULL FOR UPDATE",
+            &[&post_id, &author_id],
+        )
+        .await?
+        .is_some();
+    if !exists {
+        return Ok(None);
+    }
+
+    let Some(row) = tx
+        .query_opt(
+            "SELECT title, body, tags FROM post_revisions WHERE post_id = $1 AND revision = $2",
+            &[&post_id, &revision],
+        )
+        .await?
+    else {
+        return Ok(None);
+    };
+    let title: String = row.get(0);
+    let body: Option<String> = row.get(1);
+    let tags: Vec<String> = row.get(2);
+    let excerpt = body
+        .as_deref()
+        .map(|b| markdown::excerpt(b, EXCERPT_LENGTH));
+
+    tx.execute(
+        "UPDATE posts SET title = $2, body = $3,
+                          excerpt = CASE WHEN excerpt_custom THEN excerpt ELSE $4 END,
+                          updated_at = NOW()
+         WHERE id = $1",
+        &[&post_id, &title, &body, &excerpt],
+    )
+    .await?;
+    set_post_tags(&tx, post_id, &tags).await?;
+    refresh_search_vectors(&tx, &[post_id]).await?;
+    record_revision(&tx, post_id, author_id).await?;
+
+    tx.commit().await?;
+
+    get_post_by_id(client, post_id).await
+}
@@ -1,26 +1,269 @@
-use crate::models::tag::Tag;
-use tokio_postgres::Client;
+use crate::models::tag::{Tag, TagSort};
+use crate::services::is_unique_violation;
+use crate::services::post;
+use tokio_postgres::{Client, Transaction};
+use uuid::Uuid;
 
+/// List tags with their published post counts, optionally limited to names
+/// starting with `prefix` (case-insensitive)
 pub async fn get_all_tags(
     client: &Client,
     offset: i64,
     limit: i64,
+    sort: TagSort,
+    prefix: Option<&str>,
 ) -> Result<(Vec<Tag>, i64), tokio_postgres::Error> {
+    let pattern = prefix.map(|p| format!("{}%", post::escape_like_pattern(&p.to_lowercase())));
+    let filter = "($1::text IS NULL OR lower(t.name) LIKE $1)";
+
     // Get total count
     let total: i64 = client
-        .query_one("SELECT COUNT(*) FROM tags", &[])
+        .query_one(
+            &format!("SELECT COUNT(*) FROM tags t WHERE {}", filter),
+            &[&pattern],
+        )
         .await?
         .get(0);
 
-    // Get paginated tags
-    let rows = client
+    let order = match sort {
+        TagSort::Popular => "post_count DESC, t.name",
+        TagSort::Name => "t.name",
+        TagSort::Recent => "t.created_at DESC NULLS LAST, t.id DESC",
+    };
+    let query = format!(
+        "SELECT t.id, t.name, t.created_at,
+                (SELECT COUNT(*) FROM posts_to_tags ptt
+                 INNER JOIN posts p ON ptt.post_id = p.id
+                 WHERE ptt.tag_id = t.id AND {}) AS post_count
+         FROM tags t
+         WHERE {}
+         ORDER BY {}
+         LIMIT $2 OFFSET $3",
+        post::PUBLISHED_FILTER,
+        filter,
+        order
+    );
+    let rows = client.query(&query, &[&pattern, &limit, &offset]).await?;
+
+    let tags: Vec<Tag> = rows
+        .iter()
+        .map(|row| Tag {
+            post_count: Some(row.get(3)),
+            ..Tag::from(row)
+        })
+        .collect();
+
+    Ok((tags, total))
+}
+
+/// Condition selecting tags on at least one published post
+fn used_tag_filter() -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM posts_to_tags ptt
+                 INNER JOIN posts p ON ptt.post_id = p.id
+                 WHERE ptt.tag_id = t.id AND {})",
+        post::PUBLISHED_FILTER
+    )
+}
+
+/// Count tags on at least one published post
+pub async fn count_used_tags(client: &Client) -> Result<i64, tokio_postgres::Error> {
+    let query = format!("SELECT COUNT(*) FROM tags t WHERE {}", used_tag_filter());
+    Ok(client.query_one(&query, &[]).await?.get(0))
+}
+
+/// List the names of tags on at least one published post, by name
+pub async fn get_used_tag_names(
+    client: &Client,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<String>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT t.name FROM tags t WHERE {} ORDER BY t.name LIMIT $1 OFFSET $2",
+        used_tag_filter()
+    );
+    let rows = client.query(&query, &[&limit, &offset]).await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Result of a tag management operation that can fail on tag state
+pub enum TagOutcome {
+    Done(Tag),
+    /// The named tag does not exist
+    NotFound(String),
+    /// Another tag already has the requested name
+    NameTaken,
+    /// The tag is still attached to this many posts
+    InUse(i64),
+}
+
+/// Lock a tag row for the rest of the transaction
+async fn lock_tag(tx: &Transaction<'_>, name: &str) -> Result<Option<Tag>, tokio_postgres::Error> {
+    let row = tx
+        .query_opt(
+            "SELECT id, name, created_at FROM tags WHERE name = $1 FOR UPDATE",
+            &[&name],
+        )
+        .await?;
+    Ok(row.as_ref().map(Tag::from))
+}
+
+/// Lock the named tag rows in id order, so transactions locking overlapping
+/// tags cannot deadlock, returning the ones that exist
+async fn lock_tags(
+    tx: &Transaction<'_>,
+    names: &[&str],
+) -> Result<Vec<Tag>, tokio_postgres::Error> {
+    let rows = tx
         .query(
-            "SELECT id, name, created_at FROM tags ORDER BY name LIMIT $1 OFFSET $2",
-            &[&limit, &offset],
+            "SELECT id, name, created_at FROM tags WHERE name = ANY($1) ORDER BY id FOR UPDATE",
+            &[&names],
         )
         .await?;
+    Ok(rows.iter().map(Tag::from).collect())
+}
 
-    let tags: Vec<Tag> = rows.iter().map(Tag::from).collect();
+/// Ids of posts carrying a tag, whose search vectors depend on its name
+async fn tagged_post_ids(
+    tx: &Transaction<'_>,
+    tag_id: i32,
+) -> Result<Vec<Uuid>, tokio_postgres::Error> {
+    let rows = tx
+        .query(
+            "SELECT post_id FROM posts_to_tags WHERE tag_id = $1",
+            &[&tag_id],
+        )
+        .await?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
 
-    Ok((tags, total))
+/// Replace the tag name `from` with `to` in the tag snapshots of post
+/// revisions, so restoring an older revision brings back the renamed or
+/// merged tag. Snapshots stay sorted and free of duplicates.
+async fn rename_in_revisions(
+    tx: &Transaction<'_>,
+    from: &str,
+    to: &str,
+) -> Result<u64, tokio_postgres::Error> {
+    tx.execute(
+        "UPDATE post_revisions
+         SET tags = ARRAY(SELECT DISTINCT unnest(array_replace(tags, $1, $2)) ORDER BY 1)
+         WHERE $1 = ANY(tags)",
+        &[&from, &to],
+    )
+    .await
+}
+
+/// Create a tag, returning `None` if the name is already taken
+pub async fn create_tag(client: &Client, name: &str) -> Result<Option<Tag>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "INSERT INTO tags (name, created_at) VALUES ($1, NOW())
+             ON CONFLICT (name) DO NOTHING
+             RETURNING id, name, created_at",
+            &[&name],
+        )
+        .await?;
+    Ok(row.as_ref().map(Tag::from))
+}
+
+/// Rename a tag and refresh the search vectors and revisions of its posts
+pub async fn rename_tag(
+    client: &mut Client,
+    name: &str,
+    new_name: &str,
+) -> Result<TagOutcome, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    let mut locked = lock_tags(&tx, &[name, new_name]).await?;
+    let Some(tag) = locked.iter().position(|t| t.name == name) else {
+        return Ok(TagOutcome::NotFound(name.to_string()));
+    };
+    let tag = locked.swap_remove(tag);
+    // Anything left is another tag already called `new_name`
+    if !locked.is_empty() {
+        return Ok(TagOutcome::NameTaken);
+    }
+
+    // The name can still be created between the lock and the update
+    let row = match tx
+        .query_one(
+            "UPDATE tags SET name = $2 WHERE id = $1 RETURNING id, name, created_at",
+            &[&tag.id, &new_name],
+        )
+        .await
+    {
+        Ok(row) => row,
+        Err(e) if is_unique_violation(&e, "tags_name_key") => return Ok(TagOutcome::NameTaken),
+        Err(e) => return Err(e),
+    };
+    let post_ids = tagged_post_ids(&tx, tag.id).await?;
+    post::refresh_search_vectors(&tx, &post_ids).await?;
+    rename_in_revisions(&tx, &tag.name, new_name).await?;
+    tx.commit().await?;
+
+    Ok(TagOutcome::Done(Tag::from(&row)))
+}
+
+/// Move every post from `source` to `target` and delete `source`.
+/// Posts already carrying both tags keep a single `target` link, and
+/// revisions naming `source` name `target` instead.
+pub async fn merge_tags(
+    client: &mut Client,
+    source: &str,
+    target: &str,
+) -> Result<TagOutcome, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    let mut locked = lock_tags(&tx, &[source, target]).await?;
+    let Some(from) = locked.iter().position(|t| t.name == source) else {
+        return Ok(TagOutcome::NotFound(source.to_string()));
+    };
+    let from = locked.swap_remove(from);
+    let Some(into) = locked.pop() else {
+        return Ok(TagOutcome::NotFound(target.to_string()));
+    };
+
+    let post_ids = tagged_post_ids(&tx, from.id).await?;
+    tx.execute(
+        "INSERT INTO posts_to_tags (post_id, tag_id)
+         SELECT post_id, $2 FROM posts_to_tags WHERE tag_id = $1
+         ON CONFLICT DO NOTHING",
+        &[&from.id, &into.id],
+    )
+    .await?;
+    // Cascades to the remaining `posts_to_tags` rows of the source tag
+    tx.execute("DELETE FROM tags WHERE id = $1", &[&from.id])
+        .await?;
+    post::refresh_search_vectors(&tx, &post_ids).await?;
+    rename_in_revisions(&tx, &from.name, &into.name).await?;
+    tx.commit().await?;
+
+    Ok(TagOutcome::Done(into))
+}
+
+/// Delete a tag that is not attached to any post, including deleted posts
+pub async fn delete_tag(
+    client: &mut Client,
+    name: &str,
+) -> Result<TagOutcome, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    let Some(tag) = lock_tag(&tx, name).await? else {
+        return Ok(TagOutcome::NotFound(name.to_string()));
+    };
+
+    let uses: i64 = tx
+        .query_one(
+            "SELECT COUNT(*) FROM posts_to_tags WHERE tag_id = $1",
+            &[&tag.id],
+        )
+        .await?
+        .get(0);
+    if uses > 0 {
+        return Ok(TagOutcome::InUse(uses));
+    }
+
+    tx.execute("DELETE FROM tags WHERE id = $1", &[&tag.id])
+        .await?;
+    tx.commit().await?;
+
+    Ok(TagOutcome::Done(tag))
 }
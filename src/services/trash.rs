@@ -0,0 +1,42 @@
+use crate::database::DbPool;
+use std::time::Duration;
+use tokio_postgres::Client;
+
+/// Permanently delete posts that were soft-deleted more than `retention` ago,
+/// returning how many were removed. Tag links, comments, reactions, revisions
+/// and trending events go with them through `ON DELETE CASCADE`.
+pub async fn purge_deleted_posts(
+    client: &Client,
+    retention: Duration,
+) -> Result<u64, tokio_postgres::Error> {
+    client
+        .execute(
+            "DELETE FROM posts
+             WHERE deleted_at < NOW() - make_interval(secs => $1::float8)",
+            &[&retention.as_secs_f64()],
+        )
+        .await
+}
+
+/// Purge expired deleted posts every `interval`, starting immediately
+pub fn spawn_purger(pool: DbPool, retention: Duration, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let client = match pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::error!("Failed to get a connection to purge deleted posts: {}", e);
+                    continue;
+                }
+            };
+            match purge_deleted_posts(&client, retention).await {
+                Ok(0) => {}
+                Ok(n) => tracing::info!("Purged {} deleted posts", n),
+                Err(e) => tracing::error!("Failed to purge deleted posts: {}", e),
+            }
+        }
+    });
+}
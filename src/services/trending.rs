@@ -0,0 +1,127 @@
+use crate::database::DbPool;
+use crate::models::post::TrendingWindow;
+use crate::services::post::PUBLISHED_FILTER;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio_postgres::{Client, Transaction};
+use uuid::Uuid;
+
+/// Advisory lock key held while recomputing so replicas do not refresh concurrently
+const TRENDING_LOCK_KEY: i64 = 0x6178_756d_7472_6e64; // "axumtrnd"
+
+/// Number of ranked posts kept per window
+const TRENDING_SIZE: i64 = 1000;
+
+/// Score weight of each event kind
+const VIEW_WEIGHT: f64 = 1.0;
+const LIKE_WEIGHT: f64 = 5.0;
+const BOOKMARK_WEIGHT: f64 = 8.0;
+
+/// Record an engagement event feeding the trending rankings. Removals are
+/// recorded at the time of the event they undo, so both decay alike and cancel.
+pub async fn record_event(
+    tx: &Transaction<'_>,
+    post_id: Uuid,
+    kind: &str,
+    count: i32,
+    at: DateTime<Utc>,
+) -> Result<(), tokio_postgres::Error> {
+    tx.execute(
+        "INSERT INTO post_events (post_id, kind, count, created_at) VALUES ($1, $2, $3, $4)",
+        &[&post_id, &kind, &count, &at],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Recompute every trending window from `post_events` and prune events older than
+/// the longest window. Returns `false` if another replica holds the refresh lock.
+///
+/// Each event contributes its kind's weight times `2^(-age / half_life)`, so recent
+/// engagement outranks older engagement of the same size.
+pub async fn refresh_trending(client: &mut Client) -> Result<bool, tokio_postgres::Error> {
+    let tx = client.transaction().await?;
+    let locked: bool = tx
+        .query_one(
+            "SELECT pg_try_advisory_xact_lock($1)",
+            &[&TRENDING_LOCK_KEY],
+        )
+        .await?
+        .get(0);
+    if !locked {
+        return Ok(false);
+    }
+
+    let query = format!(
+        "INSERT INTO trending_posts (period, post_id, score, computed_at)
+         SELECT $1, e.post_id, SUM(
+                    e.count * CASE e.kind
+                        WHEN 'like' THEN {like}
+                        WHEN 'bookmark' THEN {bookmark}
+                        ELSE {view}
+                    END
+                    * power(2, -EXTRACT(EPOCH FROM NOW() - e.created_at)::float8 / $3::float8)
+                ) AS score, NOW()
+         FROM post_events e
+         INNER JOIN posts p ON e.post_id = p.id
+         WHERE e.created_at > NOW() - make_interval(secs => $2::float8) AND {filter}
+         GROUP BY e.post_id
+         HAVING SUM(e.count) > 0
+         ORDER BY score DESC
+         LIMIT {size}",
+        like = LIKE_WEIGHT,
+        bookmark = BOOKMARK_WEIGHT,
+        view = VIEW_WEIGHT,
+        filter = PUBLISHED_FILTER,
+        size = TRENDING_SIZE,
+    );
+
+    for window in TrendingWindow::ALL {
+        tx.execute(
+            "DELETE FROM trending_posts WHERE period = $1",
+            &[&window.key()],
+        )
+        .await?;
+        tx.execute(
+            &query,
+            &[&window.key(), &window.seconds(), &window.half_life()],
+        )
+        .await?;
+    }
+    // Posts whose reactions were all removed again are left with rounding noise
+    tx.execute("DELETE FROM trending_posts WHERE score <= 0", &[])
+        .await?;
+
+    let longest = TrendingWindow::Month.seconds();
+    tx.execute(
+        "DELETE FROM post_events WHERE created_at < NOW() - make_interval(secs => $1::float8)",
+        &[&longest],
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Recompute trending rankings every `interval`, starting immediately
+pub fn spawn_refresher(pool: DbPool, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let mut client = match pool.get().await {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::error!("Failed to get a connection to refresh trending: {}", e);
+                    continue;
+                }
+            };
+            match refresh_trending(&mut client).await {
+                Ok(true) => tracing::debug!("Refreshed trending posts"),
+                Ok(false) => tracing::debug!("Trending refresh already running elsewhere"),
+                Err(e) => tracing::error!("Failed to refresh trending posts: {}", e),
+            }
+        }
+    });
+}
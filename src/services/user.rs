@@ -0,0 +1,191 @@
+use crate::models::user::{User, UserProfile};
+use crate::services::is_unique_violation;
+use crate::services::post::{PUBLISHED_FILTER, refresh_search_vectors};
+use tokio_postgres::{Client, Transaction};
+use uuid::Uuid;
+
+/// Rename a user within the caller's transaction, keeping the old name in
+/// `username_history` so links to their posts redirect. Returns `None` if
+/// another user already has the name; the transaction must then be dropped.
+pub async fn change_username(
+    tx: &Transaction<'_>,
+    user_id: Uuid,
+    username: &str,
+) -> Result<Option<User>, tokio_postgres::Error> {
+    let row = tx
+        .query_one(
+            "SELECT id, username, image FROM users WHERE id = $1 FOR UPDATE",
+            &[&user_id],
+        )
+        .await?;
+    let user = User::from(&row);
+    if user.username == username {
+        return Ok(Some(user));
+    }
+
+    let taken = tx
+        .query_opt("SELECT 1 FROM users WHERE username = $1", &[&username])
+        .await?
+        .is_some();
+    if taken {
+        return Ok(None);
+    }
+
+    // Someone else may take the name between the check and the update
+    let row = match tx
+        .query_one(
+            "UPDATE users SET username = $2, updated_at = NOW() WHERE id = $1
+             RETURNING id, username, image",
+            &[&user_id, &username],
+        )
+        .await
+    {
+        Ok(row) => row,
+        Err(e) if is_unique_violation(&e, "users_username_key") => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // The new name now belongs to this user, even if someone else once used it
+    tx.execute(
+        "DELETE FROM username_history WHERE username = $1",
+        &[&username],
+    )
+    .await?;
+    tx.execute(
+        "INSERT INTO username_history (username, user_id, created_at)
+         VALUES ($1, $2, NOW())
+         ON CONFLICT (username) DO UPDATE
+         SET user_id = EXCLUDED.user_id, created_at = EXCLUDED.created_at",
+        &[&user.username, &user_id],
+    )
+    .await?;
+
+    // The author's username is part of every post's search vector
+    let post_ids: Vec<Uuid> = tx
+        .query("SELECT id FROM posts WHERE created_by = $1", &[&user_id])
+        .await?
+        .iter()
+        .map(|r| r.get(0))
+        .collect();
+    refresh_search_vectors(tx, &post_ids).await?;
+
+    Ok(Some(User::from(&row)))
+}
+
+/// Set or clear the profile image of a user: the URL of one of its files and
+/// the uploaded image it came from. Returns the user and the id of the upload
+/// the previous avatar came from, if any, so it can be deleted.
+pub async fn set_avatar(
+    client: &mut Client,
+    user_id: Uuid,
+    avatar: Option<(Uuid, &str)>,
+) -> Result<(User, Option<Uuid>), tokio_postgres::Error> {
+    let (media_id, image) = avatar.unzip();
+    let tx = client.transaction().await?;
+
+    let previous: Option<Uuid> = tx
+        .query_one(
+            "SELECT avatar_media_id FROM users WHERE id = $1 FOR UPDATE",
+            &[&user_id],
+        )
+        .await?
+        .get(0);
+    let row = tx
+        .query_one(
+            "UPDATE users SET image = $2, avatar_media_id = $3, updated_at = NOW()
+             WHERE id = $1
+             RETURNING id, username, image",
+            &[&user_id, &image, &media_id],
+        )
+        .await?;
+
+    tx.commit().await?;
+    Ok((
+        User::from(&row),
+        previous.filter(|&id| Some(id) != media_id),
+    ))
+}
+
+/// Get the public profile of `username` with post and follower counts.
+/// `followed_by_me` is filled in when `viewer` is given.
+pub async fn get_profile(
+    client: &Client,
+    username: &str,
+    viewer: Option<Uuid>,
+) -> Result<Option<UserProfile>, tokio_postgres::Error> {
+    let query = format!(
+        "SELECT u.id, u.username, u.image, u.bio, u.links, u.created_at,
+                (SELECT COUNT(*) FROM posts p WHERE p.created_by = u.id AND {}),
+                (SELECT COUNT(*) FROM user_follows f WHERE f.followee_id = u.id),
+                (SELECT COUNT(*) FROM user_follows f WHERE f.follower_id = u.id),
+                CASE WHEN $2::uuid IS NOT NULL THEN EXISTS (
+                    SELECT 1 FROM user_follows f
+                    WHERE f.follower_id = $2 AND f.followee_id = u.id
+                ) END
+         FROM users u
+         WHERE u.username = $1",
+        PUBLISHED_FILTER
+    );
+    let row = client.query_opt(&query, &[&username, &viewer]).await?;
+    Ok(row.as_ref().map(UserProfile::from))
+}
+
+/// Get the id of the user currently named `username`
+pub async fn get_user_id(
+    client: &Client,
+    username: &str,
+) -> Result<Option<Uuid>, tokio_postgres::Error> {
+    let row = client
+        .query_opt("SELECT id FROM users WHERE username = $1", &[&username])
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Current username of a user who used to be called `username`
+pub async fn resolve_moved_username(
+    client: &Client,
+    username: &str,
+) -> Result<Option<String>, tokio_postgres::Error> {
+    let row = client
+        .query_opt(
+            "SELECT u.username FROM username_history h
+             INNER JOIN users u ON h.user_id = u.id
+             WHERE h.username = $1",
+            &[&username],
+        )
+        .await?;
+    Ok(row.map(|r| r.get(0)))
+}
+
+/// Update the bio and links of a user; `None` fields are left unchanged
+pub async fn update_profile(
+    tx: &Transaction<'_>,
+    user_id: Uuid,
+    bio: Option<&str>,
+    links: Option<&[String]>,
+) -> Result<(), tokio_postgres::Error> {
+    tx.execute(
+        "UPDATE users SET bio = NULLIF(COALESCE($2, bio), ''), links = COALESCE($3, links),
+                          updated_at = NOW()
+         WHERE id = $1",
+        &[&user_id, &bio, &links],
+    )
+    .await?;
+    Ok(())
+}
+
+/// Follow or unfollow a user. Both operations are idempotent.
+pub async fn set_follow(
+    client: &Client,
+    follower_id: Uuid,
+    followee_id: Uuid,
+    active: bool,
+) -> Result<(), tokio_postgres::Error> {
+    let query = if active {
+        "INSERT INTO user_follows (follower_id, followee_id, created_at) VALUES ($1, $2, NOW())
+         ON CONFLICT DO NOTHING"
+    } else {
+        "DELETE FROM user_follows WHERE follower_id = $1 AND followee_id = $2"
+    };
+    client.execute(query, &[&follower_id, &followee_id]).await?;
+    Ok(())
+}
@@ -0,0 +1,328 @@
+use crate::database::DbPool;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+/// Longest prefix of a User-Agent used to tell anonymous visitors apart
+const MAX_USER_AGENT_LEN: usize = 128;
+
+/// Most views of one post counted per IP address within the dedup window,
+/// however many user agents the address presents
+const MAX_VIEWS_PER_ADDRESS: u32 = 10;
+
+/// Who viewed a post
+pub enum Visitor {
+    User(Uuid),
+    Anonymous { ip: String, user_agent: String },
+}
+
+impl Visitor {
+    /// Key of the visitor, or of their address when anonymous. Only a truncated
+    /// hash is kept so raw IPs and user agents never sit in memory.
+    fn keys(&self) -> ([u8; 16], Option<[u8; 16]>) {
+        match self {
+            Visitor::User(id) => (hash(&format!("user:{}", id)), None),
+            Visitor::Anonymous { ip, user_agent } => {
+                let user_agent: String = user_agent.chars().take(MAX_USER_AGENT_LEN).collect();
+                (
+                    hash(&format!("anon:{}|{}", ip, user_agent)),
+                    Some(hash(&format!("ip:{}", ip))),
+                )
+            }
+        }
+    }
+}
+
+fn hash(value: &str) -> [u8; 16] {
+    let digest = Sha256::digest(value.as_bytes());
+    let mut key = [0u8; 16];
+    key.copy_from_slice(&digest[..16]);
+    key
+}
+
+type SeenKey = (Uuid, [u8; 16]);
+
+/// Recently counted views. Each map has a queue of its keys in the order
+/// their timestamps were set, so expired and oldest entries are dropped from
+/// the front without scanning the maps.
+#[derive(Default)]
+struct Seen {
+    /// Last counted view per (post, visitor)
+    visitors: HashMap<SeenKey, Instant>,
+    visitor_order: VecDeque<(SeenKey, Instant)>,
+    /// Start of the window and views counted in it per (post, address)
+    addresses: HashMap<SeenKey, (Instant, u32)>,
+    address_order: VecDeque<(SeenKey, Instant)>,
+}
+
+impl Seen {
+    fn forget_expired(&mut self, now: Instant, window: Duration) {
+        while let Some(&(key, at)) = self.visitor_order.front()
+            && now.duration_since(at) >= window
+        {
+            self.pop_visitor(key, at);
+        }
+        while let Some(&(key, at)) = self.address_order.front()
+            && now.duration_since(at) >= window
+        {
+            self.pop_address(key, at);
+        }
+    }
+
+    /// Drop the oldest entries until there is room for one more of each
+    fn evict_oldest(&mut self, max: usize) {
+        while self.visitors.len() >= max
+            && let Some((key, at)) = self.visitor_order.front().copied()
+        {
+            self.pop_visitor(key, at);
+        }
+        while self.addresses.len() >= max
+            && let Some((key, at)) = self.address_order.front().copied()
+        {
+            self.pop_address(key, at);
+        }
+    }
+
+    /// Pop the front of the visitor queue, removing its entry unless it was
+    /// set again since
+    fn pop_visitor(&mut self, key: SeenKey, at: Instant) {
+        self.visitor_order.pop_front();
+        if self.visitors.get(&key) == Some(&at) {
+            self.visitors.remove(&key);
+        }
+    }
+
+    fn pop_address(&mut self, key: SeenKey, at: Instant) {
+        self.address_order.pop_front();
+        if self
+            .addresses
+            .get(&key)
+            .is_some_and(|(since, _)| *since == at)
+        {
+            self.addresses.remove(&key);
+        }
+    }
+}
+
+/// Buffers post views in memory and writes them to `posts.view_count` in batches.
+///
+/// Views are deduplicated per visitor: a visitor viewing the same post again
+/// within `dedup_window` is not counted twice, and an address counts at most
+/// `MAX_VIEWS_PER_ADDRESS` times. At most `max_seen` visitors are remembered;
+/// past that the oldest are forgotten, so they may be counted again. Counts
+/// accumulated between flushes are applied with a single `UPDATE`, so serving
+/// a post never waits on a row lock.
+pub struct ViewRecorder {
+    dedup_window: Duration,
+    max_seen: usize,
+    seen: Mutex<Seen>,
+    /// Views not yet written to the database
+    pending: Mutex<HashMap<Uuid, i64>>,
+}
+
+impl ViewRecorder {
+    pub fn new(dedup_window: Duration, max_seen: usize) -> Self {
+        Self {
+            dedup_window,
+            max_seen: max_seen.max(1),
+            seen: Mutex::new(Seen::default()),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a view of `post_id` by `visitor`. Returns whether the view was counted.
+    pub fn record(&self, post_id: Uuid, visitor: &Visitor) -> bool {
+        let (visitor_key, address_key) = visitor.keys();
+        let window = self.dedup_window;
+        let now = Instant::now();
+        {
+            let mut guard = self.seen.lock().unwrap();
+            let seen = &mut *guard;
+            if let Some(last) = seen.visitors.get(&(post_id, visitor_key))
+                && now.duration_since(*last) < window
+            {
+                return false;
+            }
+            if let Some(address_key) = address_key
+                && let Some((since, views)) = seen.addresses.get(&(post_id, address_key))
+                && now.duration_since(*since) < window
+                && *views >= MAX_VIEWS_PER_ADDRESS
+            {
+                return false;
+            }
+            seen.forget_expired(now, window);
+            seen.evict_oldest(self.max_seen);
+
+            seen.visitors.insert((post_id, visitor_key), now);
+            seen.visitor_order.push_back(((post_id, visitor_key), now));
+            if let Some(address_key) = address_key {
+                let key = (post_id, address_key);
+                let entry = seen.addresses.entry(key).or_insert((now, 0));
+                let restarted = entry.1 == 0 || now.duration_since(entry.0) >= window;
+                if restarted {
+                    *entry = (now, 0);
+                }
+                entry.1 += 1;
+                if restarted {
+                    seen.address_order.push_back((key, now));
+                }
+            }
+        }
+
+        *self.pending.lock().unwrap().entry(post_id).or_insert(0) += 1;
+        true
+    }
+
+    /// Write buffered counts to the database and forget expired fingerprints.
+    /// Counts are put back into the buffer if the write fails.
+    pub async fn flush(&self, client: &Client) -> Result<u64, tokio_postgres::Error> {
+        self.seen
+            .lock()
+            .unwrap()
+            .forget_expired(Instant::now(), self.dedup_window);
+
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let (ids, counts): (Vec<Uuid>, Vec<i64>) = batch.iter().map(|(id, n)| (*id, *n)).unzip();
+        // Bump the counters and record the views as trending events in one statement
+        let result = client
+            .execute(
+                "WITH v AS (SELECT * FROM unnest($1::uuid[], $2::bigint[]) AS v(id, n)),
+                 updated AS (
+                     UPDATE posts SET view_count = view_count + v.n
+                     FROM v WHERE posts.id = v.id
+                     RETURNING posts.id, v.n
+                 )
+                 INSERT INTO post_events (post_id, kind, count)
+                 SELECT id, 'view', n FROM updated",
+                &[&ids, &counts],
+            )
+            .await;
+
+        if result.is_err() {
+            let mut pending = self.pending.lock().unwrap();
+            for (id, n) in batch {
+                *pending.entry(id).or_insert(0) += n;
+            }
+        }
+        result
+    }
+
+    /// Flush buffered views every `interval` until the process exits
+    pub fn spawn_flusher(self: Arc<Self>, pool: DbPool, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if self.pending.lock().unwrap().is_empty() {
+                    continue;
+                }
+                let client = match pool.get().await {
+                    Ok(client) => client,
+                    Err(e) => {
+                        tracing::error!("Failed to get a connection to flush view counts: {}", e);
+                        continue;
+                    }
+                };
+                match self.flush(&client).await {
+                    Ok(n) => tracing::debug!("Flushed view counts for {} posts", n),
+                    Err(e) => tracing::error!("Failed to flush view counts: {}", e),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn anonymous(ip: &str, user_agent: &str) -> Visitor {
+        Visitor::Anonymous {
+            ip: ip.to_string(),
+            user_agent: user_agent.to_string(),
+        }
+    }
+
+    #[test]
+    fn counts_a_visitor_once_per_post() {
+        let views = ViewRecorder::new(WINDOW, 100);
+        let (post, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let user = Visitor::User(Uuid::new_v4());
+        assert!(views.record(post, &user));
+        assert!(!views.record(post, &user));
+        assert!(views.record(other, &user));
+
+        assert!(views.record(post, &anonymous("10.0.0.1", "Firefox")));
+        assert!(!views.record(post, &anonymous("10.0.0.1", "Firefox")));
+        assert!(views.record(post, &anonymous("10.0.0.1", "Chrome")));
+        assert!(views.record(post, &anonymous("10.0.0.2", "Firefox")));
+    }
+
+    #[test]
+    fn ignores_user_agent_text_past_the_limit() {
+        let views = ViewRecorder::new(WINDOW, 100);
+        let post = Uuid::new_v4();
+        let prefix = "x".repeat(MAX_USER_AGENT_LEN);
+        assert!(views.record(post, &anonymous("10.0.0.1", &format!("{}a", prefix))));
+        assert!(!views.record(post, &anonymous("10.0.0.1", &format!("{}b", prefix))));
+    }
+
+    #[test]
+    fn limits_views_per_address_across_user_agents() {
+        let views = ViewRecorder::new(WINDOW, 100);
+        let post = Uuid::new_v4();
+        let counted = (0..50)
+            .filter(|n| views.record(post, &anonymous("10.0.0.1", &format!("agent {}", n))))
+            .count();
+        assert_eq!(counted, MAX_VIEWS_PER_ADDRESS as usize);
+        assert!(views.record(post, &Visitor::User(Uuid::new_v4())));
+        assert!(views.record(Uuid::new_v4(), &anonymous("10.0.0.1", "agent 0")));
+    }
+
+    #[test]
+    fn forgets_the_oldest_visitors_when_full() {
+        let views = ViewRecorder::new(WINDOW, 3);
+        let post = Uuid::new_v4();
+        let users: Vec<_> = (0..4).map(|_| Visitor::User(Uuid::new_v4())).collect();
+        for user in &users {
+            assert!(views.record(post, user));
+        }
+        assert_eq!(views.seen.lock().unwrap().visitors.len(), 3);
+        // The first visitor was evicted to make room, the others are remembered
+        assert!(!views.record(post, &users[3]));
+        assert!(!views.record(post, &users[2]));
+        assert!(views.record(post, &users[0]));
+
+        let anonymous_views = ViewRecorder::new(WINDOW, 3);
+        for n in 0..10 {
+            let ip = format!("10.0.0.{}", n);
+            assert!(anonymous_views.record(post, &anonymous(&ip, "Firefox")));
+        }
+        let seen = anonymous_views.seen.lock().unwrap();
+        assert_eq!(seen.visitors.len(), 3);
+        assert_eq!(seen.addresses.len(), 3);
+        assert_eq!(seen.visitor_order.len(), 3);
+        assert_eq!(seen.address_order.len(), 3);
+    }
+
+    #[test]
+    fn forgets_expired_visitors() {
+        let views = ViewRecorder::new(Duration::ZERO, 3);
+        let post = Uuid::new_v4();
+        let user = Visitor::User(Uuid::new_v4());
+        for _ in 0..5 {
+            assert!(views.record(post, &user));
+        }
+        assert!(views.seen.lock().unwrap().visitor_order.len() <= 1);
+    }
+}
@@ -0,0 +1,82 @@
+use crate::auth::JwtKeys;
+use crate::cache::TtlCache;
+use crate::config::Config;
+use crate::database::DbPool;
+use crate::markdown::RenderedBody;
+use crate::models::post::{BodyFormat, Post};
+use crate::services::view::ViewRecorder;
+use crate::storage::{LocalStorage, Storage};
+use axum::extract::FromRef;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// Maximum number of posts whose related posts are cached at once
+const RELATED_CACHE_CAPACITY: usize = 10_000;
+
+/// Maximum number of rendered post bodies cached at once
+const RENDER_CACHE_CAPACITY: usize = 2_000;
+
+/// Maximum total size of the rendered post bodies cached at once
+const RENDER_CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// Identifies one rendering of a post body: the post, its `updated_at` and the format
+pub type RenderKey = (Uuid, DateTime<Utc>, BodyFormat);
+
+/// Shared application state passed to every handler
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: DbPool,
+    pub config: Arc<Config>,
+    pub jwt: Option<Arc<JwtKeys>>,
+    pub views: Arc<ViewRecorder>,
+    /// Related posts per source post, computed for the maximum limit
+    pub related: Arc<TtlCache<Uuid, Vec<Post>>>,
+    /// Rendered bodies; keys include `updated_at`, so edits invalidate them
+    pub rendered: Arc<TtlCache<RenderKey, RenderedBody>>,
+    /// Uploaded media files
+    pub storage: Arc<dyn Storage>,
+    /// Permits for image processing, bounding the memory uploads can use at once
+    pub image_jobs: Arc<Semaphore>,
+}
+
+impl AppState {
+    /// Build the application state, preparing token verification keys from `config`
+    ///
+    /// # Errors
+    /// Returns an error if a configured JWT key cannot be parsed.
+    pub fn new(pool: DbPool, config: Config) -> Result<Self, jsonwebtoken::errors::Error> {
+        let jwt = JwtKeys::from_config(&config.jwt)?.map(Arc::new);
+        let views = Arc::new(ViewRecorder::new(
+            config.views.dedup_window,
+            config.views.max_visitors,
+        ));
+        let related = Arc::new(TtlCache::new(
+            config.related.cache_ttl,
+            RELATED_CACHE_CAPACITY,
+        ));
+        let rendered = Arc::new(
+            TtlCache::new(config.render_cache_ttl, RENDER_CACHE_CAPACITY)
+                .with_max_weight(RENDER_CACHE_MAX_BYTES, RenderedBody::size),
+        );
+        let storage = Arc::new(LocalStorage::new(config.media.dir.clone()));
+        let image_jobs = Arc::new(Semaphore::new(config.media.processing_concurrency));
+        Ok(Self {
+            pool,
+            config: Arc::new(config),
+            jwt,
+            views,
+            related,
+            rendered,
+            storage,
+            image_jobs,
+        })
+    }
+}
+
+impl FromRef<AppState> for DbPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where uploaded media files are kept. Keys are `/`-separated relative paths
+/// such as `{media_id}/w640.webp`; implementations may map them to files,
+/// object names in an S3-compatible bucket, and so on.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Store `data` under `key`, replacing any existing file
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()>;
+
+    /// Read the file stored under `key`, or `None` if there is none
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// Remove the file stored under `key`; missing files are not an error
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Files under a directory on the local filesystem
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve a key below the root, rejecting absolute paths, `..` and the
+    /// empty key, which would name the root itself
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        let relative = Path::new(key);
+        if key.is_empty()
+            || !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid storage key: {}", key),
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(key)?;
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        // Write to a temporary file first so readers never see partial files
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, data).await?;
+        tokio::fs::rename(&tmp, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(key)?).await {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        // Drop the directory with the last file; fails while others remain
+        if let Some(dir) = path.parent().filter(|&dir| dir != self.root) {
+            let _ = tokio::fs::remove_dir(dir).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_relative_keys_below_the_root() {
+        let storage = LocalStorage::new("/srv/media");
+        assert_eq!(
+            storage.path("abc/w640.webp").unwrap(),
+            Path::new("/srv/media/abc/w640.webp")
+        );
+    }
+
+    #[test]
+    fn rejects_keys_leaving_the_root() {
+        let storage = LocalStorage::new("/srv/media");
+        for key in ["../secret", "abc/../../secret", "/etc/passwd", "./abc", ""] {
+            let error = storage.path(key).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{}", key);
+        }
+    }
+}